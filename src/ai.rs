@@ -0,0 +1,147 @@
+//! Pig behaviours: how a pig picks its direction each time it is aligned to the grid.
+//!
+//! Set per pig by the "ai" property of its starting cell (see Props), e.g. "ai=chase".
+//! Random walkers draw from their own seeded random number generator (see Mover::rng),
+//! so that replays and undo play out the same way every time.
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+/// Pig behaviour.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ai {
+	#[default]
+	Patrol, // walk straight, turn around when bumping into something
+	Follow, // follow the wall on the left (left-hand rule)
+	Chase,  // walk the shortest path towards the player, patrol if there is none
+	Random, // walk to a random neighbouring cell, turn around only at dead ends
+}
+
+/// All behaviours, in the order the editor cycles through them.
+pub const AIS: [Ai; 4] = [Ai::Patrol, Ai::Follow, Ai::Chase, Ai::Random];
+
+impl Ai {
+	/// The behaviour after this one, wrapping around (see AIS).
+	pub fn next(self) -> Ai {
+		let i = AIS.iter().position(|&a| a == self).unwrap();
+		AIS[(i + 1) % AIS.len()]
+	}
+}
+
+impl fmt::Display for Ai {
+	fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+		write!(f, "{}", format!("{:?}", self).to_lowercase())
+	}
+}
+
+impl FromStr for Ai {
+	type Err = Error;
+
+	/// Parse a behaviour name. Inverse of Display.
+	///
+	///     use game::prelude::*;
+	///     let ai: Ai = "chase".parse().unwrap();
+	///     assert_eq!(ai, Ai::Chase);
+	///     assert_eq!(ai.to_string(), "chase");
+	fn from_str(s: &str) -> Result<Ai> {
+		match AIS.iter().find(|a| a.to_string() == s) {
+			Some(&a) => Ok(a),
+			None => Err(Error::Parse(format!("unknown ai \"{}\" (patrol, follow, chase or random)", s))),
+		}
+	}
+}
+
+/// Seed for the random number generator of the pig starting at grid position p,
+/// unless its cell sets one (see Props::seed).
+pub fn default_seed(p: Pt) -> u64 {
+	((p.0 as u32 as u64) << 32 | p.1 as u32 as u64) ^ 0x9e37_79b9_7f4a_7c15
+}
+
+/// Next state of a xorshift random number generator. Never returns 0 (unless given 0).
+pub fn next_random(state: u64) -> u64 {
+	let mut x = state;
+	x ^= x << 13;
+	x ^= x >> 7;
+	x ^= x << 17;
+	x
+}
+
+/// Set the move intent of pig i according to its behaviour.
+/// Called when the pig is aligned to the grid.
+pub fn steer_pig(g: &mut Gamestate, i: usize) {
+	let dir = match g.mv[i].ai {
+		Ai::Patrol => return, // keeps going, see Mover::on_bump
+		Ai::Follow => follow_wall(g, i),
+		Ai::Chase => chase(g, i),
+		Ai::Random => random_walk(g, i),
+	};
+	if dir != Dir::None {
+		g.mv[i].intent = dir;
+	}
+}
+
+// Left-hand rule: turn left if possible, else go straight, else right, else back.
+fn follow_wall(g: &Gamestate, i: usize) -> Dir {
+	let heading = heading(&g.mv[i]);
+	let turns = [heading.turn_left(), heading, heading.turn_right(), heading.opposite()];
+	let speed = g.mv[i].speed();
+	turns.iter().copied().find(|&d| g.can_move(i, d, speed)).unwrap_or(Dir::None)
+}
+
+// First step of the shortest path to the player, over cells the pig can walk on, around other movers.
+// None if the player cannot be reached.
+fn chase(g: &Gamestate, i: usize) -> Dir {
+	let start = g.mv[i].grid();
+	let goal = g.player().approx_grid();
+	let (min, max) = match g.map.bounds() {
+		Some(bounds) => bounds,
+		None => return Dir::None,
+	};
+	let blocked: Vec<Pt> = g.mv.iter().skip(1).filter(|m| !m.is_dead()).map(|m| m.approx_grid()).collect();
+	let open = |p: Pt| p.0 >= min.0 && p.0 <= max.0 && p.1 >= min.1 && p.1 <= max.1 && g.mv[i].can_walk(g.map.at(p));
+
+	// breadth-first search, remembering the first step taken towards each cell.
+	let mut first_step = BTreeMap::new();
+	let mut todo = VecDeque::new();
+	todo.push_back(start);
+	while let Some(p) = todo.pop_front() {
+		if p == goal {
+			return first_step.get(&p).copied().unwrap_or(Dir::None);
+		}
+		for &d in &LRUD {
+			let next = p + d.vector();
+			if next == start || first_step.contains_key(&next) || !open(next) || (next != goal && blocked.contains(&next)) {
+				continue;
+			}
+			let step = if p == start { d } else { first_step[&p] };
+			first_step.insert(next, step);
+			todo.push_back(next);
+		}
+	}
+	Dir::None
+}
+
+// A random direction the pig can move in, other than back where it came from.
+// Back if there is no other way.
+fn random_walk(g: &mut Gamestate, i: usize) -> Dir {
+	let back = heading(&g.mv[i]).opposite();
+	let speed = g.mv[i].speed();
+	let mut options: Vec<Dir> = LRUD.iter().copied().filter(|&d| d != back && g.can_move(i, d, speed)).collect();
+	if options.is_empty() {
+		options.push(back);
+	}
+	g.mv[i].rng = next_random(g.mv[i].rng);
+	options[(g.mv[i].rng % options.len() as u64) as usize]
+}
+
+// Direction the pig is walking (or was walking, if it has stopped).
+fn heading(m: &Mover) -> Dir {
+	match m.intent {
+		Dir::None => m.look,
+		d => d,
+	}
+}
@@ -0,0 +1,251 @@
+//! Plain text map format, one character per block, for editing maps in a text editor.
+//!
+//! Optional header lines start with ';' and hold metadata and game rules (see Rules), e.g.:
+//!
+//! ```text
+//! ; title: Water world
+//! ; author: barnex
+//! ; hint: Ice cubes float
+//! ; par: 34
+//! ; push: 2
+//! ; origin: 29 17
+//! ```
+//! followed by the map rows. The first character of the first row is at "origin" (default 0 0).
+//! Spaces are empty blocks, trailing spaces may be omitted. See LEGEND for all other characters.
+//! Blocks go in their default layer (see block_layer).
+//!
+//! Maps that stack blocks (e.g. a key on ice) are written one layer at a time,
+//! each layer starting with its own header:
+//!
+//! ```text
+//! ; layer: floor
+//! ; origin: 1 1
+//! ==
+//! ; layer: objects
+//! ; origin: 2 1
+//! b
+//! ```
+//!
+//! Cell properties (see Props) follow the rows, one cell per line: "; props: x y radius=5".
+//! As do wires (see Map::connect), from cell x1 y1 to cell x2 y2: "; wire: x1 y1 x2 y2".
+use crate::prelude::*;
+
+/// Character for each block type.
+/// Colored blocks come in the order blue, green, red, yellow.
+pub const LEGEND: [(Block, char); 46] = [
+	(NONE, ' '),
+	(BRICK, '#'),
+	(WATER, '~'),
+	(ICE, '='),
+	(LAVA, '%'),
+	(WHIRLPOOL, '@'),
+	(LOCK_B, 'B'),
+	(LOCK_G, 'G'),
+	(LOCK_R, 'R'),
+	(LOCK_Y, 'Y'),
+	(KEY_B, 'b'),
+	(KEY_G, 'g'),
+	(KEY_R, 'r'),
+	(KEY_Y, 'y'),
+	(TOGGLE_CLOSED_B, '1'),
+	(TOGGLE_CLOSED_G, '2'),
+	(TOGGLE_CLOSED_R, '3'),
+	(TOGGLE_CLOSED_Y, '4'),
+	(TOGGLE_OPEN_B, '5'),
+	(TOGGLE_OPEN_G, '6'),
+	(TOGGLE_OPEN_R, '7'),
+	(TOGGLE_OPEN_Y, '8'),
+	(BUTTON_B, 'i'),
+	(BUTTON_G, 'j'),
+	(BUTTON_R, 'k'),
+	(BUTTON_Y, 'l'),
+	(PLATE_B, 'm'),
+	(PLATE_G, 'n'),
+	(PLATE_R, 'o'),
+	(PLATE_Y, 'p'),
+	(ARROW_L, '<'),
+	(ARROW_R, '>'),
+	(ARROW_U, '^'),
+	(ARROW_D, 'v'),
+	(TELEPORTER, 'T'),
+	(PLAYER, 'P'),
+	(EXIT, 'E'),
+	(GOAL, '.'),
+	(PLAYER_ON_GOAL, '+'),
+	(CRATE, 'X'),
+	(ICECUBE, 'I'),
+	(CRATE_ON_GOAL, '*'),
+	(PIG_L, '{'),
+	(PIG_R, '}'),
+	(PIG_U, 'A'),
+	(PIG_D, 'V'),
+];
+
+/// Character for block b, None if it has no character.
+pub fn block_to_char(b: Block) -> Option<char> {
+	LEGEND.iter().find(|(blk, _)| *blk == b).map(|(_, c)| *c)
+}
+
+/// Block for character c, None if it does not represent a block.
+pub fn char_to_block(c: char) -> Option<Block> {
+	LEGEND.iter().find(|(_, ch)| *ch == c).map(|(b, _)| *b)
+}
+
+/// Parse a map in text format.
+///
+///     use game::prelude::*;
+///     let text = "; title: tiny\n; origin: 1 1\n#P E#\n";
+///     let map = game::ascii::parse(text).unwrap();
+///     assert_eq!(map.meta.title, "tiny");
+///     assert!(map.has(Pt(2, 1), PLAYER));
+///     assert_eq!(game::ascii::format(&map).unwrap(), text);
+pub fn parse(text: &str) -> Result<Map> {
+	let mut map = Map::new();
+	let mut layer = None; // None: blocks go in their default layer
+	let mut origin = Pt(0, 0);
+	let mut dy = 0; // row, relative to origin
+
+	for (i, line) in text.lines().enumerate() {
+		if let Some(header) = line.strip_prefix(';') {
+			let (key, value) = match header.find(':') {
+				Some(c) => (header[..c].trim(), header[c + 1..].trim()),
+				None => return Err(Error::Parse(format!("line {}: header needs \"key: value\": {}", i + 1, line))),
+			};
+			match key {
+				"title" => map.meta.title = value.into(),
+				"author" => map.meta.author = value.into(),
+				"hint" => map.meta.hint = value.into(),
+				"par" => map.meta.par = Some(parse_num(value, i)?),
+				"push" => map.rules.push = parse_num(value, i)?,
+				"origin" => {
+					let xy: Vec<&str> = value.split_whitespace().collect();
+					if xy.len() != 2 {
+						return Err(Error::Parse(format!("line {}: origin needs 2 numbers: {}", i + 1, value)));
+					}
+					origin = Pt(parse_num(xy[0], i)?, parse_num(xy[1], i)?);
+					dy = 0;
+				}
+				"props" => {
+					let mut fields = value.splitn(3, char::is_whitespace);
+					let (x, y) = match (fields.next(), fields.next()) {
+						(Some(x), Some(y)) => (parse_num(x, i)?, parse_num(y, i)?),
+						_ => return Err(Error::Parse(format!("line {}: props need a position: {}", i + 1, value))),
+					};
+					match fields.next().unwrap_or_default().parse() {
+						Ok(props) => map.set_props(Pt(x, y), props),
+						Err(e) => return Err(Error::Parse(format!("line {}: {}", i + 1, e))),
+					}
+				}
+				"wire" => {
+					let xy: Vec<&str> = value.split_whitespace().collect();
+					if xy.len() != 4 {
+						return Err(Error::Parse(format!("line {}: wire needs 4 numbers: {}", i + 1, value)));
+					}
+					let n: Vec<i32> = xy.iter().map(|v| parse_num(v, i)).collect::<Result<_>>()?;
+					map.connect(Pt(n[0], n[1]), Pt(n[2], n[3]));
+				}
+				"layer" => {
+					layer = match layer_by_name(value) {
+						Some(l) => Some(l),
+						None => return Err(Error::Parse(format!("line {}: unknown layer \"{}\"", i + 1, value))),
+					};
+					origin = Pt(0, 0);
+					dy = 0;
+				}
+				_ => return Err(Error::Parse(format!("line {}: unknown header \"{}\"", i + 1, key))),
+			}
+			continue;
+		}
+
+		for (dx, c) in line.chars().enumerate() {
+			let b = match char_to_block(c) {
+				Some(b) => b,
+				None => return Err(Error::Parse(format!("line {}, column {}: unknown block '{}'", i + 1, dx + 1, c))),
+			};
+			let p = origin + Pt(dx as i32, dy);
+			match (b, layer) {
+				(NONE, _) => (),
+				(_, Some(l)) => map.layers[l].set(p, b),
+				(_, None) => map.set(p, b),
+			}
+		}
+		dy += 1;
+	}
+	Ok(map)
+}
+
+// Parse a header number, reporting line i (0-based) on error.
+fn parse_num<T: std::str::FromStr>(s: &str, i: usize) -> Result<T> {
+	match s.parse() {
+		Ok(v) => Ok(v),
+		Err(_) => Err(Error::Parse(format!("line {}: invalid number: {}", i + 1, s))),
+	}
+}
+
+/// Format a map in text format. Inverse of parse.
+/// Fails on blocks without a character (see LEGEND).
+pub fn format(map: &Map) -> Result<String> {
+	let mut text = String::new();
+	let meta = &map.meta;
+	for (key, value) in &[("title", &meta.title), ("author", &meta.author), ("hint", &meta.hint)] {
+		if !value.is_empty() {
+			text += &format!("; {}: {}\n", key, value);
+		}
+	}
+	if let Some(par) = meta.par {
+		text += &format!("; par: {}\n", par);
+	}
+	if map.rules != Rules::default() {
+		text += &format!("; push: {}\n", map.rules.push);
+	}
+
+	if is_flat(map) {
+		// one grid, blocks of all layers mixed
+		let [floor, objects] = &map.layers;
+		text = format_rows(text, map.bounds(), |p| floor[p].max(objects[p]))?; // at most one is non-empty
+	} else {
+		for (l, layer) in map.layers.iter().enumerate() {
+			if let Some(bounds) = layer.bounds() {
+				text += &format!("; layer: {}\n", LAYER_NAMES[l]);
+				text = format_rows(text, Some(bounds), |p| layer[p])?;
+			}
+		}
+	}
+	for (p, props) in map.all_props() {
+		text += &format!("; props: {} {} {}\n", p.0, p.1, props);
+	}
+	for (from, to) in map.wires() {
+		text += &format!("; wire: {} {} {} {}\n", from.0, from.1, to.0, to.1);
+	}
+	Ok(text)
+}
+
+// Can the map be written as a single grid?
+// I.e., are all blocks in their default layer, and never on top of each other?
+fn is_flat(map: &Map) -> bool {
+	let [floor, objects] = &map.layers;
+	floor.blocks().iter().all(|&(p, b)| block_layer(b) == FLOOR && objects[p] == NONE)
+		&& objects.blocks().iter().all(|&(_, b)| block_layer(b) == OBJECTS)
+}
+
+// Append origin and rows, for the bounding box (inclusive), to text.
+fn format_rows<F: Fn(Pt) -> Block>(mut text: String, bounds: Option<(Pt, Pt)>, block: F) -> Result<String> {
+	let (min, max) = match bounds {
+		Some(bounds) => bounds,
+		None => return Ok(text),
+	};
+	text += &format!("; origin: {} {}\n", min.0, min.1);
+	for y in min.1..=max.1 {
+		let mut row = String::new();
+		for x in min.0..=max.0 {
+			let b = block(Pt(x, y));
+			match block_to_char(b) {
+				Some(c) => row.push(c),
+				None => return Err(Error::Other(format!("block {} at {:?} has no text representation", b, Pt(x, y)))),
+			}
+		}
+		text += row.trim_end();
+		text.push('\n');
+	}
+	Ok(text)
+}
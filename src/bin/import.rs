@@ -0,0 +1,35 @@
+use game::encoding;
+use game::prelude::*;
+use game::xsb;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Import Sokoban levels in XSB format (.xsb, .sok).
+/// Usage: import FILE OUTDIR
+/// Writes each level in FILE as a separate map in OUTDIR, named after FILE: e.g. "microban-001.json".
+pub fn main() {
+	if let Err(e) = main_() {
+		eprintln!("{}", e.report());
+		process::exit(1);
+	}
+}
+
+pub fn main_() -> Result<()> {
+	let args: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+	if args.len() != 2 {
+		return Err(Error::Other("usage: import FILE OUTDIR".into()));
+	}
+	let (file, dir) = (&args[0], &args[1]);
+
+	let text = fs::read_to_string(file).map_err(|e| Error::from(e).in_file(file))?;
+	let levels = xsb::parse(&text).map_err(|e| e.in_file(file))?;
+	let stem = file.file_stem().unwrap_or_else(|| file.as_os_str()).to_string_lossy();
+	fs::create_dir_all(dir)?;
+	for (i, level) in levels.iter().enumerate() {
+		encoding::save(level, &Path::new(dir).join(format!("{}-{:03}.json", stem, i + 1)))?;
+	}
+	Ok(())
+}
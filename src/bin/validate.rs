@@ -0,0 +1,67 @@
+use game::encoding;
+use game::prelude::*;
+use game::validate;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+/// Check maps for structural problems (missing exit, duplicate players, ...).
+/// Checks all maps in assets/maps, or the map files and directories given as arguments.
+/// For directories, also checks the campaign manifest and reports maps missing from it.
+/// Exits with a non-zero status if any problems are found, so it can be used to gate level submissions.
+pub fn main() {
+	match main_() {
+		Ok(true) => (),
+		Ok(false) => process::exit(1),
+		Err(e) => {
+			eprintln!("{}", e.report());
+			process::exit(2);
+		}
+	}
+}
+
+// returns true if all maps are fine.
+pub fn main_() -> Result<bool> {
+	let mut args: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+	if args.is_empty() {
+		args.push(PathBuf::from("assets/maps"));
+	}
+
+	let mut ok = true;
+	let mut files = Vec::new();
+	for arg in args {
+		if arg.is_dir() {
+			let ls = encoding::ls_maps(&arg)?;
+			match Campaign::load(&arg) {
+				Ok(campaign) => {
+					let listed = campaign.maps();
+					for f in ls.iter().filter(|f| !listed.contains(f)) {
+						println!("{}: not part of the campaign", f.to_string_lossy());
+						ok = false;
+					}
+				}
+				Err(e) => {
+					println!("{}", e.report());
+					ok = false;
+				}
+			}
+			files.extend(ls);
+		} else {
+			files.push(arg);
+		}
+	}
+
+	for f in &files {
+		let problems = match encoding::load(f) {
+			Ok(map) => validate::check(&map).iter().map(|p| p.to_string()).collect(),
+			Err(Error::File(_, e)) => vec![e.report()], // file name is printed below
+			Err(e) => vec![e.report()],
+		};
+		for p in &problems {
+			println!("{}: {}", f.to_string_lossy(), p);
+		}
+		ok = ok && problems.is_empty();
+	}
+	Ok(ok)
+}
@@ -14,6 +14,53 @@ pub fn block_arrow_dir(b: Block) -> Dir {
 	}
 }
 
+/// Is the block one of the named blocks below?
+/// Other values have no meaning (nor texture).
+pub fn block_is_known(b: Block) -> bool {
+	matches!(
+		b,
+		NONE | BRICK | WATER..=WHIRLPOOL | LOCK_B..=ARROW_D | TELEPORTER | PLAYER..=PLAYER_ON_GOAL | CRATE..=CRATE_ON_GOAL | PIG_L..=PLATE_Y
+	)
+}
+
+/// The block left on the map when unstaging block b into a mover:
+/// GOAL for movers placed on a goal, NONE otherwise.
+pub fn block_underneath(b: Block) -> Block {
+	match b {
+		PLAYER_ON_GOAL | CRATE_ON_GOAL => GOAL,
+		_ => NONE,
+	}
+}
+
+/// The layer a block goes in by default (see map.rs):
+/// terrain on the FLOOR, everything else (keys, arrows, movers, ...) on OBJECTS.
+pub fn block_layer(b: Block) -> usize {
+	match b {
+		NONE | BRICK | WATER..=WHIRLPOOL | TELEPORTER | EXIT | GOAL | PLATE_B..=PLATE_Y => FLOOR,
+		_ => OBJECTS,
+	}
+}
+
+/// The block that receiver b turns into when signaled through a wire (see Map::connect):
+/// locks open, toggles flip, arrows reverse. Other blocks are not receivers and stay as they are.
+pub fn block_signaled(b: Block) -> Block {
+	match b {
+		LOCK_B..=LOCK_Y => NONE,
+		TOGGLE_CLOSED_B..=TOGGLE_CLOSED_Y => b + 4,
+		TOGGLE_OPEN_B..=TOGGLE_OPEN_Y => b - 4,
+		ARROW_L => ARROW_R,
+		ARROW_R => ARROW_L,
+		ARROW_U => ARROW_D,
+		ARROW_D => ARROW_U,
+		_ => b,
+	}
+}
+
+/// Is the block a trigger, that can send signals through wires (see Map::connect)?
+pub fn block_is_trigger(b: Block) -> bool {
+	matches!(b, KEY_B..=KEY_Y | BUTTON_B..=BUTTON_Y | PLATE_B..=PLATE_Y)
+}
+
 /// Is the block an arrow?
 pub fn block_is_arrow(b: Block) -> bool {
 	block_arrow_dir(b) != Dir::None
@@ -24,6 +71,8 @@ pub const NONE: u8 = 0;
 pub const BRICK: u8 = 4;
 pub const WATER: u8 = 8;
 pub const ICE: u8 = 9;
+pub const LAVA: u8 = 10; // burns the player
+pub const WHIRLPOOL: u8 = 11; // drowns the player
 pub const LOCK_B: u8 = 12;
 pub const LOCK_G: u8 = 13;
 pub const LOCK_R: u8 = 14;
@@ -48,16 +97,24 @@ pub const ARROW_L: u8 = 32;
 pub const ARROW_R: u8 = 33;
 pub const ARROW_U: u8 = 34;
 pub const ARROW_D: u8 = 35;
+pub const TELEPORTER: u8 = 36; // sends movers to its partner, see Props::target
 pub const PLAYER: u8 = 40;
 pub const EXIT: u8 = 41;
+pub const GOAL: u8 = 42; // complete the map by covering all goals with crates
+pub const PLAYER_ON_GOAL: u8 = 43; // staging only
 pub const CRATE: u8 = 44;
 pub const ICECUBE: u8 = 45;
+pub const CRATE_ON_GOAL: u8 = 46; // staging only
 pub const PIG: u8 = 48; // canonical pig, used as type
 pub const PIG_L: u8 = 48;
 pub const PIG_R: u8 = 49;
 pub const PIG_U: u8 = 50;
 pub const PIG_D: u8 = 51;
+pub const PLATE_B: u8 = 52; // pressure plate: holds toggles flipped while a mover stands on it
+pub const PLATE_G: u8 = 53;
+pub const PLATE_R: u8 = 54;
+pub const PLATE_Y: u8 = 55;
 
-pub const NUM_BLOCKS: usize = 52;
+pub const NUM_BLOCKS: usize = 56;
 
 pub const PLAYER_L: Tex = 340;
@@ -0,0 +1,201 @@
+use crate::encoding;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the campaign manifest in a map directory.
+pub const CAMPAIGN_FILE: &str = "campaign.json";
+
+/// The order in which the maps in a directory are played, grouped into worlds.
+/// Read from the directory's manifest (CAMPAIGN_FILE). Maps not listed there are not part of the campaign.
+/// Without manifest, all maps form a single world, in alphabetical order.
+#[derive(Serialize, Deserialize)]
+pub struct Campaign {
+	pub worlds: Vec<World>,
+	#[serde(skip)]
+	dir: PathBuf, // map directory, map names are relative to it
+	#[serde(skip)]
+	checksums: HashMap<String, u64>, // map name -> Map::checksum, for matching progress records
+}
+
+/// A group of maps with a common theme (water, ice, sokoban, ...).
+#[derive(Serialize, Deserialize)]
+pub struct World {
+	pub name: String,
+	pub maps: Vec<String>, // file names, in playing order
+	#[serde(default)]
+	pub unlock: Unlock, // requirements to play this world
+}
+
+/// What the player must have done before a world can be played.
+/// Nothing by default.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Unlock {
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub worlds: Vec<String>, // names of worlds that must be completed
+	#[serde(default)]
+	pub maps_completed: usize, // number of campaign maps that must be completed (in any world)
+}
+
+impl Campaign {
+	/// Load the campaign for a map directory.
+	pub fn load(dir: &Path) -> Result<Campaign> {
+		let manifest = dir.join(CAMPAIGN_FILE);
+		if !manifest.exists() {
+			return Campaign::alphabetical(dir);
+		}
+		let load = || -> Result<Campaign> {
+			let mut c: Campaign = serde_json::from_reader(io::BufReader::new(File::open(&manifest)?))?;
+			c.dir = dir.to_owned();
+			c.check()?;
+			c.load_checksums();
+			Ok(c)
+		};
+		load().map_err(|e| e.in_file(&manifest))
+	}
+
+	// All maps in the directory as one world, in alphabetical order.
+	fn alphabetical(dir: &Path) -> Result<Campaign> {
+		let maps = encoding::ls_maps(dir)?;
+		let mut c = Campaign {
+			worlds: vec![World {
+				name: String::new(),
+				maps: maps.iter().map(|f| encoding::file_name(f)).collect(),
+				unlock: Unlock::default(),
+			}],
+			dir: dir.to_owned(),
+			checksums: HashMap::new(),
+		};
+		c.load_checksums();
+		Ok(c)
+	}
+
+	// Read all maps once, so that checking progress (see is_unlocked) needs no disk access.
+	// Maps that cannot be read are left out, and count as not completed.
+	fn load_checksums(&mut self) {
+		for m in self.worlds.iter().flat_map(|w| &w.maps) {
+			if let Ok(map) = encoding::load_unchecked(&self.dir.join(m)) {
+				self.checksums.insert(m.clone(), map.checksum());
+			}
+		}
+	}
+
+	// Check that all maps exist and unlock requirements refer to existing worlds.
+	fn check(&self) -> Result<()> {
+		for w in &self.worlds {
+			for m in &w.maps {
+				if !self.dir.join(m).exists() {
+					return Err(Error::Parse(format!("world '{}': map '{}' not found", w.name, m)));
+				}
+			}
+			for name in &w.unlock.worlds {
+				if self.world(name).is_none() {
+					return Err(Error::Parse(format!(
+						"world '{}': unlock requires unknown world '{}'",
+						w.name, name
+					)));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// All maps, in playing order.
+	pub fn maps(&self) -> Vec<PathBuf> {
+		self.worlds.iter().flat_map(|w| w.maps.iter().map(|m| self.dir.join(m))).collect()
+	}
+
+	/// The first map of the campaign.
+	pub fn first(&self) -> Result<PathBuf> {
+		match self.maps().first() {
+			Some(f) => Ok(f.clone()),
+			None => Err(Error::NoMaps(self.dir.clone())),
+		}
+	}
+
+	/// The map delta positions after curr (before, for negative delta).
+	/// Returns None when going past the last (or before the first) map.
+	/// If progress is given, refuses to enter a world that has not been unlocked.
+	pub fn next(&self, curr: &Path, delta: i32, progress: Option<&Progress>) -> Result<Option<PathBuf>> {
+		let maps = self.maps();
+		let i = match maps.iter().position(|f| f == curr) {
+			Some(i) => i as i32 + delta,
+			None => {
+				return Err(Error::Other(format!(
+					"current map {} is not part of the campaign in {}",
+					curr.to_string_lossy(),
+					self.dir.to_string_lossy()
+				)))
+			}
+		};
+		if i < 0 || i >= maps.len() as i32 {
+			return Ok(None);
+		}
+		let next = &maps[i as usize];
+		if let (Some(progress), Some(world)) = (progress, self.world_of(next)) {
+			if !self.is_unlocked(world, progress) {
+				return Err(Error::Locked(world.name.clone()));
+			}
+		}
+		Ok(Some(next.clone()))
+	}
+
+	/// The world with given name.
+	pub fn world(&self, name: &str) -> Option<&World> {
+		self.worlds.iter().find(|w| w.name == name)
+	}
+
+	/// The world a map file belongs to.
+	pub fn world_of(&self, map: &Path) -> Option<&World> {
+		self.worlds.iter().find(|w| w.maps.iter().any(|m| self.dir.join(m) == map))
+	}
+
+	/// Has the player met the world's unlock requirements?
+	pub fn is_unlocked(&self, world: &World, progress: &Progress) -> bool {
+		let done = |w: &World| w.maps.iter().filter(|m| self.is_completed(m, progress)).count();
+		let total: usize = self.worlds.iter().map(done).sum();
+		total >= world.unlock.maps_completed
+			&& world.unlock.worlds.iter().all(|name| match self.world(name) {
+				Some(w) => done(w) == w.maps.len(),
+				None => false,
+			})
+	}
+
+	// Has the player completed the map (file name)?
+	fn is_completed(&self, map: &str, progress: &Progress) -> bool {
+		match self.checksums.get(map) {
+			Some(&hash) => progress.record(hash).map(|r| r.completed).unwrap_or(false),
+			None => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unlock_worlds() {
+		let dir = Path::new("assets/maps");
+		let c = Campaign::load(dir).unwrap();
+		let mut progress = Progress::default();
+		let last_water = dir.join("03.json");
+		match c.next(&last_water, 1, Some(&progress)) {
+			Err(Error::Locked(world)) => assert_eq!(world, "Factory"),
+			_ => panic!("Factory should be locked"),
+		}
+		assert!(c.next(&last_water, 1, None).unwrap().is_some()); // without progress, all worlds are open
+
+		for m in &c.world("Water").unwrap().maps {
+			let f = dir.join(m);
+			let hash = encoding::load_unchecked(&f).unwrap().checksum();
+			progress.complete(MapId::new(&f, hash), Stats::default());
+		}
+		let next = c.next(&last_water, 1, Some(&progress)).unwrap();
+		assert_eq!(next, Some(dir.join("10-the-factory.json")));
+		assert!(!c.is_unlocked(c.world("Ice").unwrap(), &progress));
+	}
+}
@@ -3,7 +3,7 @@ use crate::prelude::*;
 use std::fmt;
 
 /// Dir is a direction.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Dir {
 	None = 0,
@@ -49,6 +49,22 @@ impl Dir {
 			Dir::Down => Dir::Up,
 		}
 	}
+
+	/// Direction after turning left (counterclockwise, as seen on screen).
+	pub fn turn_left(self) -> Dir {
+		match self {
+			Dir::None => Dir::None,
+			Dir::Left => Dir::Down,
+			Dir::Right => Dir::Up,
+			Dir::Up => Dir::Left,
+			Dir::Down => Dir::Right,
+		}
+	}
+
+	/// Direction after turning right (clockwise, as seen on screen).
+	pub fn turn_right(self) -> Dir {
+		self.turn_left().opposite()
+	}
 }
 
 impl fmt::Display for Dir {
@@ -1,47 +1,83 @@
 use crate::encoding;
 use crate::palette::*;
 use crate::prelude::*;
-use std::io;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
 // Size, in quanta, of a block. Ideally equal to sprite size in pixels.
 pub const GRID: i32 = 48;
 
+// Remaining input of a replay being played back.
+type Playback = std::vec::IntoIter<KeyStates>;
+
 /// Editor allows the user to draw and play maps.
 pub struct Editor {
-	file: PathBuf,        // current map file
-	staging: Map,         // current map, edit mode
-	viewport: Viewport,   // visible portion of map
-	paused: bool,         // editing or playing
-	palette: Palette,     // visible while editing
-	gamestate: Gamestate, // current map, playing mode
+	file: PathBuf,              // current map file
+	staging: Map,               // current map, edit mode
+	viewport: Viewport,         // visible portion of map
+	paused: bool,               // editing or playing
+	palette: Palette,           // visible while editing
+	layer: usize,               // layer being painted while editing (FLOOR, OBJECTS)
+	cursor: Pt,                 // cell last clicked while editing
+	typing: Option<String>,     // properties of the cursor cell, while being typed (see edit_props)
+	typing_started: bool,       // no text typed since edit_props
+	unpaired: Option<Pt>,       // teleporter placed last, waiting for its partner (see pair_teleporter)
+	wiring: Option<Pt>,         // start of the wire being drawn (see start_wire)
+	gamestate: Gamestate,       // current map, playing mode
+	playback: Option<Playback>, // recorded input replacing the keyboard, if playing back a replay
+	progress: Option<Progress>, // player's progress, if tracked
 }
 
 impl Editor {
 	/// Load file for editing.
-	pub fn new(width: i32, height: i32, file: PathBuf, paused: bool) -> Editor {
+	/// A new map is created if the file does not exist.
+	pub fn new(width: i32, height: i32, file: PathBuf, paused: bool) -> Result<Editor> {
 		let mut viewport = Viewport::new(width, height);
 		viewport.set_center(Pt(width / 2 - 4 * GRID, height / 2));
 
 		if !file.exists() {
-			try_create_empty_map(&file)
+			create_empty_map(&file)?
 		}
 
 		let gamestate = if paused {
 			Gamestate::empty()
 		} else {
-			Gamestate::load(file.clone()).expect("loading map")
+			Gamestate::load(file.clone())?
 		};
 
-		Editor {
-			palette: Palette::new(Pt(0, 0), 4, GRID, 2222, (0..NUM_BLOCKS).into_iter().collect()),
+		// only offer blocks that have a meaning (and texture)
+		let blocks = (0..NUM_BLOCKS).filter(|&b| block_is_known(b as Block)).collect();
+
+		Ok(Editor {
+			palette: Palette::new(Pt(0, 0), 4, GRID, 2222, blocks),
+			layer: FLOOR,
+			cursor: Pt(1, 1),
+			typing: None,
+			typing_started: false,
+			unpaired: None,
+			wiring: None,
 			viewport,
 			paused,
-			staging: encoding::load(&file).expect("loading map"),
+			staging: encoding::load_unchecked(&file)?,
 			gamestate,
 			file,
-		}
+			playback: None,
+			progress: None,
+		})
+	}
+
+	/// Keep track of the player's progress (maps completed, best scores, last map played)
+	/// and save it as it changes.
+	pub fn track_progress(&mut self, progress: Progress) {
+		self.progress = Some(progress);
+	}
+
+	/// Play back a recorded replay instead of taking input from the keyboard.
+	pub fn play_replay(&mut self, replay: &Replay) -> Result<()> {
+		replay.load_map()?; // check that the map has not changed
+		self.gamestate = Gamestate::load(replay.map.clone())?;
+		self.playback = Some(replay.keys().into_iter());
+		self.paused = false;
+		Ok(())
 	}
 
 	/// Called by main loop every 16.6ms (on average) to advance time.
@@ -51,25 +87,110 @@ impl Editor {
 		if self.paused {
 			return;
 		}
-		self.gamestate.tick(keys);
+		let events = match &mut self.playback {
+			Some(recorded) => self.gamestate.step(recorded.next().unwrap_or_else(KeyStates::new)), // already recorded
+			None => self.gamestate.tick(keys),
+		};
+		self.update_progress(&events);
 		self.viewport.set_center(self.gamestate.player().pos() + Pt(GRID / 2, GRID / 2));
 	}
 
+	// remember which map is being played, and which maps are completed.
+	fn update_progress(&mut self, events: &[GameEvent]) {
+		if let Some(progress) = &mut self.progress {
+			let id = self.gamestate.map_id();
+			let mut changed = false;
+			if progress.last.as_ref() != Some(id) {
+				progress.set_last(id.clone());
+				changed = true;
+			}
+			if events.contains(&GameEvent::Exit) {
+				progress.complete(id.clone(), self.gamestate.stats());
+				changed = true;
+			}
+			if changed {
+				if let Err(e) = progress.save() {
+					eprintln!("Error saving progress: {}", e.report());
+				}
+			}
+		}
+	}
+
 	/// render Editor to display.
 	pub fn render(&self, disp: &mut Display) {
 		match self.paused {
 			true => self.render_paused(disp),
 			false => self.render_playing(disp),
 		}
+		disp.set_title(&self.title());
+	}
+
+	// Window title: which map we are on, how the player is doing.
+	fn title(&self) -> String {
+		let name = self.file.file_stem().unwrap_or_default().to_string_lossy();
+		if let Some(text) = &self.typing {
+			return format!(
+				"properties of {} {}: {}_ (enter to set, escape to cancel)",
+				self.cursor.0, self.cursor.1, text
+			);
+		}
+		if self.paused {
+			let layer = LAYER_NAMES[self.layer];
+			return match (self.wiring, self.unpaired) {
+				(Some(p), _) => format!(
+					"editing {} ({} layer): click the cell to wire {} {} to (C to cancel)",
+					name, layer, p.0, p.1
+				),
+				(None, Some(p)) => format!(
+					"editing {} ({} layer): place the partner of teleporter {} {}",
+					name, layer, p.0, p.1
+				),
+				(None, None) => match self.pig_ai(self.cursor) {
+					Some(ai) => format!(
+						"editing {} ({} layer): pig at {} {} is {} (I to change)",
+						name, layer, self.cursor.0, self.cursor.1, ai
+					),
+					None => format!("editing {} ({} layer)", name, layer),
+				},
+			};
+		}
+		let meta = &self.gamestate.map.meta;
+		let name = match meta.title.as_str() {
+			"" => self.gamestate.file().file_stem().unwrap_or_default().to_string_lossy(),
+			title => title.into(),
+		};
+		let stats = self.gamestate.stats();
+		match self.gamestate.status() {
+			Status::Complete if !self.gamestate.message().is_empty() => format!("{} complete! {}", name, self.gamestate.message()),
+			Status::Complete => format!(
+				"{} complete! {} moves, {} pushes in {:.1}s. Press space to continue.",
+				name,
+				stats.moves,
+				stats.pushes,
+				stats.ticks as f32 / TICKS_PER_SECOND as f32
+			),
+			Status::Finished => "Congratulations, you completed all maps!".to_owned(),
+			_ if !self.gamestate.message().is_empty() => format!("{} - {}", name, self.gamestate.message()),
+			_ if !meta.hint.is_empty() => format!("{} - {}", name, meta.hint),
+			_ => name.into_owned(),
+		}
 	}
 
 	fn render_playing(&self, disp: &mut Display) {
 		self.viewport.render_map(disp, &self.gamestate.map);
-		self.viewport.render_movers(disp, &self.gamestate.mv);
+		match self.gamestate.status() {
+			Status::Dead(_) => self.viewport.render_movers(disp, &self.gamestate.mv[1..]), // player is gone
+			_ => self.viewport.render_movers(disp, &self.gamestate.mv),
+		}
+		match self.gamestate.status() {
+			Status::Complete | Status::Finished => render_banner(disp, EXIT as Tex),
+			_ => (),
+		}
 	}
 
 	fn render_paused(&self, disp: &mut Display) {
 		self.viewport.render_map(disp, &self.staging);
+		self.viewport.render_wires(disp, &self.staging);
 		self.palette.render(disp);
 	}
 
@@ -86,9 +207,11 @@ impl Editor {
 	}
 
 	// called when user picks a block from the blocks palette.
+	// Switches to the block's default layer, see toggle_layer() to paint other layers.
 	fn handle_click_palette(&mut self, button: Option<usize>) {
 		if let Some(button) = button {
-			self.palette.selected = button
+			self.palette.selected = button;
+			self.layer = block_layer(self.palette.buttons[button] as Block);
 		}
 	}
 
@@ -97,18 +220,48 @@ impl Editor {
 			return;
 		}
 
-		let pos = self.viewport.to_world(pos) / GRID;
-		if pos.is_neg() {
+		let pos = self.viewport.to_world(pos).div_floor(GRID);
+		if left || right {
+			self.cursor = pos;
+		}
+		if let (Some(from), true) = (self.wiring, left) {
+			self.finish_wire(from, pos);
 			return;
 		}
+		let layer = &mut self.staging.layers[self.layer];
 		if right {
-			self.staging.set(pos, NONE)
+			layer.set(pos, NONE)
 		}
 		if left {
-			self.staging.set(pos, self.palette.selected as Block)
+			let b = self.palette.buttons[self.palette.selected] as Block;
+			let new = layer[pos] != b;
+			layer.set(pos, b);
+			if b == TELEPORTER && new {
+				self.pair_teleporter(pos);
+			}
 		}
 	}
 
+	// Teleporters are placed in pairs: each newly placed teleporter becomes the partner
+	// of the one placed before it, unless that one already got a partner.
+	// Pairs can also be changed by editing the target property (see edit_props).
+	fn pair_teleporter(&mut self, pos: Pt) {
+		match self.unpaired.take() {
+			Some(other) if other != pos && self.staging.has(other, TELEPORTER) => {
+				self.set_target(pos, other);
+				self.set_target(other, pos);
+			}
+			_ => self.unpaired = Some(pos),
+		}
+	}
+
+	// Set the teleporter target property of the cell at pos.
+	fn set_target(&mut self, pos: Pt, target: Pt) {
+		let mut props = self.staging.props(pos).clone();
+		props.target = Some(target);
+		self.staging.set_props(pos, props);
+	}
+
 	pub fn handle_mouse_wheel(&mut self, x: i32, y: i32) {
 		self.pan_view(Pt(-x, -y))
 	}
@@ -119,10 +272,12 @@ impl Editor {
 			Key::Pause => self.toggle_pause(),
 			Key::ZoomIn => self.viewport.zoom_in(),
 			Key::ZoomOut => self.viewport.zoom_out(),
+			// Input is ignored while playing back a replay, or typed as text.
+			_ if self.playback.is_some() || self.is_typing() => (),
 			// Pause-dependent keys:
 			_ => match self.paused {
 				true => self.handle_key_paused(k),
-				false => self.gamestate.handle_key(k),
+				false => self.gamestate.handle_key(k, self.progress.as_ref()),
 			},
 		}
 	}
@@ -134,14 +289,112 @@ impl Editor {
 			Key::Down => self.pan_view(Pt(0, 2)),
 			Key::Up => self.pan_view(Pt(0, -2)),
 			Key::Save => self.try_save(),
+			Key::B => self.toggle_layer(),
+			Key::A => self.edit_props(),
+			Key::Wire => self.start_wire(),
+			Key::Ai => self.cycle_ai(),
 			_ => (),
 		}
 	}
 
+	// Start drawing a wire from the cursor cell (typically a trigger: key, button, plate),
+	// the next click picks the other end (typically a receiver: lock, toggle, arrow).
+	// Pressed again, cancels the wire.
+	fn start_wire(&mut self) {
+		self.wiring = match self.wiring {
+			Some(_) => None,
+			None => Some(self.cursor),
+		}
+	}
+
+	// Wire cells from and to, or remove the wire if they were already wired.
+	fn finish_wire(&mut self, from: Pt, to: Pt) {
+		self.wiring = None;
+		if self.staging.is_connected(from, to) {
+			self.staging.disconnect(from, to);
+		} else if from != to {
+			self.staging.connect(from, to);
+		}
+	}
+
+	// Give the pig in the cursor cell the next behaviour (see Ai).
+	fn cycle_ai(&mut self) {
+		if let Some(ai) = self.pig_ai(self.cursor) {
+			let mut props = self.staging.props(self.cursor).clone();
+			props.ai = match ai.next() {
+				Ai::Patrol => None, // default
+				ai => Some(ai),
+			};
+			self.staging.set_props(self.cursor, props);
+		}
+	}
+
+	// Behaviour of the pig in cell pos, None if there is no pig.
+	fn pig_ai(&self, pos: Pt) -> Option<Ai> {
+		match self.staging.at(pos).iter().any(|b| matches!(b, PIG_L..=PIG_D)) {
+			true => Some(self.staging.props(pos).ai.unwrap_or_default()),
+			false => None,
+		}
+	}
+
+	// Start typing the properties of the cursor cell (see Props for the syntax).
+	// Text input then goes to handle_text(), until finish_typing() or cancel_typing().
+	fn edit_props(&mut self) {
+		self.typing = Some(self.staging.props(self.cursor).to_string());
+		self.typing_started = true;
+	}
+
+	/// Are keystrokes being typed as text (see handle_text)?
+	pub fn is_typing(&self) -> bool {
+		self.typing.is_some()
+	}
+
+	/// Called with text input from the keyboard.
+	/// Drops the space of the key that started typing (see edit_props).
+	pub fn handle_text(&mut self, text: &str) {
+		let started = std::mem::take(&mut self.typing_started);
+		if let Some(typing) = &mut self.typing {
+			if !(started && text == " ") {
+				typing.push_str(text);
+			}
+		}
+	}
+
+	/// Delete the last character typed.
+	pub fn delete_char(&mut self) {
+		if let Some(typing) = &mut self.typing {
+			typing.pop();
+		}
+	}
+
+	/// Set the typed properties on the cursor cell.
+	/// Keeps typing if they cannot be parsed.
+	pub fn finish_typing(&mut self) {
+		if let Some(typing) = &self.typing {
+			match typing.parse() {
+				Ok(props) => {
+					self.staging.set_props(self.cursor, props);
+					self.typing = None;
+				}
+				Err(e) => eprintln!("{}", e.report()),
+			}
+		}
+	}
+
+	/// Stop typing, leaving the properties unchanged.
+	pub fn cancel_typing(&mut self) {
+		self.typing = None;
+	}
+
+	// Paint the next layer (wrapping around).
+	fn toggle_layer(&mut self) {
+		self.layer = (self.layer + 1) % NUM_LAYERS;
+	}
+
 	fn try_save(&self) {
 		let f = &self.file;
 		if let Err(e) = self.save(f) {
-			writeln!(io::stderr(), "Error saving map to {}: {}", &f.to_string_lossy(), e).unwrap();
+			eprintln!("Error saving map: {}", e.report());
 		}
 	}
 
@@ -154,10 +407,15 @@ impl Editor {
 		Ok(())
 	}
 
+	// Switch between editing and playing.
+	// Stays in edit mode if the map cannot be played (e.g. it has no player).
 	fn toggle_pause(&mut self) {
+		self.playback = None;
 		if self.paused {
-			self.save(&self.file).expect("saving map");
-			self.gamestate = Gamestate::load(self.file.clone()).expect("loading map");
+			if let Err(e) = self.save(&self.file).and_then(|_| self.load(self.file.clone())) {
+				eprintln!("{}", e.report());
+				return;
+			}
 		}
 		self.paused = !self.paused;
 	}
@@ -168,9 +426,21 @@ impl Editor {
 	}
 }
 
-fn try_create_empty_map(f: &Path) {
+// Render a band across the middle of the display, showing a (magnified) texture.
+// E.g. to signal that the map is complete.
+fn render_banner(disp: &mut Display, tex: Tex) {
+	let (w, h) = disp.size();
+	let s = 3 * GRID;
+	disp.fill_rect(Color(64, 64, 64, 255), Pt(0, (h - s) / 2 - GRID / 2), w, s + GRID);
+	disp.copy_tex(tex, Pt(w - s, h - s) / 2, s, s);
+}
+
+// New maps start out with only a player, the minimum for a map to load.
+fn create_empty_map(f: &Path) -> Result<()> {
 	println!("creating new map: '{}'", f.to_string_lossy());
-	encoding::save(&Map::new(), f).expect("error creating new map")
+	let mut map = Map::new();
+	map.set(Pt(1, 1), PLAYER);
+	encoding::save(&map, f)
 }
 
 #[inline]
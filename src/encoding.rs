@@ -1,4 +1,7 @@
+use crate::ascii;
 use crate::prelude::*;
+use crate::validate::{self, Problem};
+use serde::{Deserialize, Serialize};
 
 use std::ffi::OsStr;
 use std::fs;
@@ -7,35 +10,329 @@ use std::io;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-/// Load a map in JSON format.
+/// Version of the map file format written by save().
+///
+/// 0: {"blocks": [[...]]}, without version field.
+/// 1: adds "version" and metadata: "title", "author", "hint", "par" (all optional).
+/// 2: compact: "origin" and "size" of the bounding box of all non-empty blocks,
+///    and "rows" of run-length encoded blocks, e.g. "4*3 0 8*2" = brick, brick, brick, none, water, water.
+///    Saved with one row per line, so that maps diff well.
+/// 3: layered: "floor" and "objects", each with its own "origin", "size" and "rows" as in version 2.
+///    Empty layers are omitted.
+/// 4: adds "props": cell properties, e.g. {"at":[3,4],"radius":5}. Saved with one cell per line.
+/// 5: adds "wires": from trigger to receiver cell, e.g. {"from":[3,4],"to":[9,4]}. Saved with one wire per line.
+/// 6: adds game rules: "push" (optional, default 1).
+pub const FORMAT_VERSION: u32 = 6;
+
+// Width limit for rows of version 2 files without "size". Much wider than any real map.
+const MAX_WIDTH: i32 = 1 << 16;
+
+// Map file, as serialized.
+// Blocks are either dense (version 0, 1), compact (version 2) or compact per layer (version 3).
+#[derive(Serialize, Deserialize)]
+struct MapFile {
+	#[serde(default)]
+	version: u32,
+	#[serde(flatten)]
+	meta: Meta,
+	#[serde(flatten)]
+	rules: Rules,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	blocks: Option<Vec<Vec<Block>>>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	origin: Option<(i32, i32)>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	size: Option<(i32, i32)>,
+	#[serde(default, skip_serializing)]
+	rows: Option<Vec<String>>, // serialized by hand, see save_json()
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	floor: Option<LayerFile>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	objects: Option<LayerFile>,
+	#[serde(default, skip_serializing)]
+	props: Vec<CellProps>, // serialized by hand, see save_json()
+	#[serde(default, skip_serializing)]
+	wires: Vec<Wire>, // serialized by hand, see save_json()
+}
+
+// One layer of a map file (version 3).
+#[derive(Serialize, Deserialize)]
+struct LayerFile {
+	origin: (i32, i32),
+	size: (i32, i32),
+	#[serde(default, skip_serializing)]
+	rows: Vec<String>, // serialized by hand, see save_json()
+}
+
+// Properties of one cell (version 4).
+#[derive(Serialize, Deserialize)]
+struct CellProps {
+	at: (i32, i32),
+	#[serde(flatten)]
+	props: Props,
+}
+
+// A wire between two cells (version 5), see Map::connect.
+#[derive(Serialize, Deserialize)]
+struct Wire {
+	from: Pt,
+	to: Pt,
+}
+
+/// Load a map for playing, in text format (.txt, see ascii.rs) or JSON format (otherwise).
+/// Maps that cannot be played (unknown blocks, no player, several players) are rejected,
+/// with the row and column of the offending blocks.
 pub fn load(p: &Path) -> Result<Map> {
 	println!("loading {}", p.to_string_lossy());
+	load_unchecked(p).and_then(|map| check(map).map_err(|e| e.in_file(p)))
+}
+
+/// Load a map without checking that it can be played.
+/// Used for editing, so that broken maps can be opened and fixed.
+pub fn load_unchecked(p: &Path) -> Result<Map> {
+	let map = match is_text(p) {
+		true => fs::read_to_string(p).map_err(Error::from).and_then(|text| ascii::parse(&text)),
+		false => load_json(p),
+	};
+	map.map_err(|e| e.in_file(p))
+}
+
+// Reject maps with fatal problems (see validate.rs).
+fn check(map: Map) -> Result<Map> {
+	let fatal: Vec<Problem> = validate::check(&map).into_iter().filter(|p| p.is_fatal()).collect();
+	match fatal.len() {
+		0 => Ok(map),
+		_ => Err(Error::Invalid(fatal)),
+	}
+}
+
+/// Save a map, in text format (.txt, see ascii.rs) or JSON format (otherwise).
+pub fn save(map: &Map, p: &Path) -> Result<()> {
+	let result = match is_text(p) {
+		true => ascii::format(map).and_then(|text| fs::write(p, text).map_err(Error::from)),
+		false => save_json(map, p),
+	};
+	result.map_err(|e| e.in_file(p))?;
+	println!("wrote {}", p.to_string_lossy());
+	Ok(())
+}
+
+// Does the file name indicate the text format?
+fn is_text(p: &Path) -> bool {
+	p.extension() == Some(OsStr::new("txt"))
+}
+
+/// Load a map in JSON format.
+/// Reads all versions up to FORMAT_VERSION, dense or compact.
+fn load_json(p: &Path) -> Result<Map> {
 	let f = File::open(p)?;
 	let b = io::BufReader::new(f);
-	let map = serde_json::from_reader(b)?;
+	let file: MapFile = serde_json::from_reader(b)?;
+	if file.version > FORMAT_VERSION {
+		return Err(Error::Parse(format!(
+			"map format version {} is newer than supported ({})",
+			file.version, FORMAT_VERSION
+		)));
+	}
+	let layered = file.floor.is_some() || file.objects.is_some();
+	let mut map = Map::new();
+	match (file.blocks, file.rows, layered) {
+		(Some(blocks), None, false) => map = decode_dense(&blocks),
+		(None, Some(rows), false) => decode_rows(&rows, file.origin.unwrap_or((0, 0)), file.size, |p, b| map.set(p, b))?,
+		(None, None, _) => {
+			for (l, layer) in [file.floor, file.objects].iter().enumerate() {
+				if let Some(layer) = layer {
+					decode_rows(&layer.rows, layer.origin, Some(layer.size), |p, b| map.layers[l].set(p, b))
+						.map_err(|e| Error::Parse(format!("{}: {}", LAYER_NAMES[l], e)))?;
+				}
+			}
+		}
+		_ => return Err(Error::Parse("need either \"blocks\", \"rows\" or layers".into())),
+	};
+	for cell in file.props {
+		cell.props.check()?;
+		map.set_props(Pt(cell.at.0, cell.at.1), cell.props);
+	}
+	for wire in file.wires {
+		map.connect(wire.from, wire.to);
+	}
+	map.rules = file.rules;
+	map.meta = file.meta;
 	Ok(map)
 }
 
-/// Save a map in JSON format.
-pub fn save(map: &Map, p: &Path) -> Result<()> {
+/// Save a map in (compact) JSON format, version FORMAT_VERSION.
+fn save_json(map: &Map, p: &Path) -> Result<()> {
 	let f = File::create(p)?;
 	let mut b = io::BufWriter::new(f);
-	serde_json::to_writer(&mut b, map)?;
+	let file = MapFile {
+		version: FORMAT_VERSION,
+		meta: map.meta.clone(),
+		rules: map.rules,
+		blocks: None,
+		origin: None,
+		size: None,
+		rows: None,
+		floor: None,
+		objects: None,
+		props: vec![],
+		wires: vec![],
+	};
+
+	// headers by serde, then rows by hand: one per line.
+	let header = serde_json::to_string(&file)?;
+	write!(b, "{}", &header[..header.len() - 1])?;
+	for (l, layer) in map.layers.iter().enumerate() {
+		let (origin, size, rows) = encode_rows(layer);
+		if rows.is_empty() {
+			continue;
+		}
+		let header = serde_json::to_string(&LayerFile {
+			origin,
+			size,
+			rows: vec![],
+		})?;
+		write!(b, ",\"{}\":{},\"rows\":[", LAYER_NAMES[l], &header[..header.len() - 1])?;
+		for (i, row) in rows.iter().enumerate() {
+			let sep = if i == 0 { "" } else { "," };
+			write!(b, "{}\n{}", sep, serde_json::to_string(row)?)?;
+		}
+		write!(b, "\n]}}")?;
+	}
+	let mut props = Vec::new();
+	for (p, cell_props) in map.all_props() {
+		let cell = CellProps {
+			at: (p.0, p.1),
+			props: cell_props.clone(),
+		};
+		props.push(serde_json::to_string(&cell)?);
+	}
+	write_list(&mut b, "props", &props)?;
+	let mut wires = Vec::new();
+	for (from, to) in map.wires() {
+		wires.push(serde_json::to_string(&Wire { from, to })?);
+	}
+	write_list(&mut b, "wires", &wires)?;
+	writeln!(b, "}}")?;
+
 	b.flush()?;
-	println!("wrote {}", p.to_string_lossy());
 	Ok(())
 }
 
+// Write a list field by hand, one (JSON) item per line. Nothing for an empty list.
+fn write_list<W: Write>(w: &mut W, name: &str, items: &[String]) -> Result<()> {
+	for (i, item) in items.iter().enumerate() {
+		match i {
+			0 => write!(w, ",\"{}\":[\n{}", name, item)?,
+			_ => write!(w, ",\n{}", item)?,
+		}
+	}
+	if !items.is_empty() {
+		write!(w, "\n]")?;
+	}
+	Ok(())
+}
+
+// Run-length encode the bounding box of all non-empty blocks in a layer.
+// Returns origin, size and rows. Trailing empty blocks are omitted from rows.
+fn encode_rows(layer: &Layer) -> ((i32, i32), (i32, i32), Vec<String>) {
+	let (min, max) = match layer.bounds() {
+		Some(bounds) => bounds,
+		None => return ((0, 0), (0, 0), vec![]), // empty layer
+	};
+
+	let mut rows = Vec::new();
+	for y in min.1..=max.1 {
+		let mut runs: Vec<(Block, usize)> = Vec::new();
+		for x in min.0..=max.0 {
+			let b = layer[Pt(x, y)];
+			match runs.last_mut() {
+				Some(run) if run.0 == b => run.1 += 1,
+				_ => runs.push((b, 1)),
+			}
+		}
+		if runs.last().map(|run| run.0) == Some(NONE) {
+			runs.pop();
+		}
+		let tokens: Vec<String> = runs
+			.iter()
+			.map(|&(b, n)| if n == 1 { b.to_string() } else { format!("{}*{}", b, n) })
+			.collect();
+		rows.push(tokens.join(" "));
+	}
+	((min.0, min.1), (max.0 - min.0 + 1, max.1 - min.1 + 1), rows)
+}
+
+// Blocks of format versions 0 and 1: rows of block ids, starting at (0, 0).
+fn decode_dense(blocks: &[Vec<Block>]) -> Map {
+	let mut map = Map::new();
+	for (y, row) in blocks.iter().enumerate() {
+		for (x, &b) in row.iter().enumerate() {
+			map.set(Pt(x as i32, y as i32), b);
+		}
+	}
+	map
+}
+
+// Inverse of encode_rows: calls set for each non-empty block.
+fn decode_rows<F: FnMut(Pt, Block)>(rows: &[String], origin: (i32, i32), size: Option<(i32, i32)>, mut set: F) -> Result<()> {
+	if let Some(size) = size {
+		if rows.len() as i32 != size.1 {
+			return Err(Error::Parse(format!("have {} rows, size says {}", rows.len(), size.1)));
+		}
+	}
+	let width = size.map(|s| s.0).unwrap_or(MAX_WIDTH);
+	for (dy, row) in rows.iter().enumerate() {
+		let mut dx = 0;
+		for token in row.split_whitespace() {
+			let (b, n) = match parse_run(token) {
+				Some(run) if run.1 > 0 => run,
+				_ => return Err(Error::Parse(format!("row {}: invalid block or run: \"{}\"", dy, token))),
+			};
+			// check before expanding, so that huge runs fail fast.
+			if n > width - dx {
+				return Err(Error::Parse(format!("row {}: more blocks than the width ({})", dy, width)));
+			}
+			for _ in 0..n {
+				if b != NONE {
+					set(Pt(origin.0 + dx, origin.1 + dy as i32), b);
+				}
+				dx += 1;
+			}
+		}
+	}
+	Ok(())
+}
+
+// Parse "b" or "b*n": block b, repeated n times.
+fn parse_run(token: &str) -> Option<(Block, i32)> {
+	let mut parts = token.splitn(2, '*');
+	let b = parts.next()?.parse().ok()?;
+	let n = match parts.next() {
+		Some(n) => n.parse().ok()?,
+		None => 1,
+	};
+	Some((b, n))
+}
+
 /// Separate a map's blocks into static blocks (Map) and Movers.
 pub fn unstage(staging: &Map) -> (Map, Vec<Mover>) {
 	let mut map = Map::new();
+	map.rules = staging.rules;
+	map.meta = staging.meta.clone();
 	let mut movers = vec![Mover::new(Pt(1, 1) * GRID, PLAYER)];
 
-	for (iy, row) in staging.blocks.iter().enumerate() {
-		for (ix, blk) in row.iter().enumerate() {
-			let grid = Pt(ix as i32, iy as i32);
-
-			if let Some(mover) = Mover::unstage(grid * GRID, *blk) {
+	for (l, layer) in staging.layers.iter().enumerate() {
+		for (grid, blk) in layer.blocks() {
+			if let Some(mut mover) = Mover::unstage(grid * GRID, blk) {
+				if mover.typ() == PIG {
+					let props = staging.props(grid);
+					mover.ai = props.ai.unwrap_or_default();
+					mover.rng = props.seed.unwrap_or_else(|| default_seed(grid)).max(1); // xorshift gets stuck at 0
+				}
+				if block_underneath(blk) != NONE {
+					map.set(grid, block_underneath(blk));
+				}
 				// player is special: there is exactly one, and it's the first mover
 				if mover.typ() == PLAYER {
 					movers[0].pos = mover.pos;
@@ -43,54 +340,112 @@ pub fn unstage(staging: &Map) -> (Map, Vec<Mover>) {
 					movers.push(mover)
 				}
 			} else {
-				map.set(grid, *blk);
+				map.layers[l].set(grid, blk);
 			}
 		}
 	}
+	for (p, props) in staging.all_props() {
+		map.set_props(p, props.clone());
+	}
+	for (from, to) in staging.wires() {
+		map.connect(from, to);
+	}
 	(map, movers)
 }
 
-/// Given a map file, find the next map (alphabetically) in the same directory.
-/// Find the previous map if delta = -1.
-pub fn find_next_map(curr: &Path, delta: i32) -> Result<PathBuf> {
-	let parent = match curr.parent() {
-		None => return GenError::new(format!("find_next_map: file '{}' has not parent directory", curr.to_string_lossy())),
-		Some(p) => p,
-	};
-	let ls = ls_maps(parent)?;
-	if let Some(i) = ls.iter().position(|x| x == curr) {
-		let mut i = (i as i32) + delta;
-		if i < 0 {
-			i = (ls.len() as i32) - 1;
-		}
-		if i >= (ls.len() as i32) {
-			i = 0;
-		};
-		Ok(ls[i as usize].clone())
-	} else {
-		GenError::new(format!(
-			"find_next_map: current map {} not found in {}",
-			curr.to_string_lossy(),
-			parent.to_string_lossy()
-		))
+/// The campaign of the directory that map file curr is in.
+pub fn campaign_of(curr: &Path) -> Result<Campaign> {
+	match curr.parent() {
+		Some(dir) => Campaign::load(dir),
+		None => Err(Error::Other(format!("file '{}' has no parent directory", curr.to_string_lossy()))),
 	}
 }
 
-/// Find the first map (alphabetically) in a directory.
+/// Find the first map of the campaign in a directory.
 pub fn find_map1(dir: &Path) -> Result<PathBuf> {
-	let ls = ls_maps(dir)?;
-	match ls.len() {
-		0 => GenError::new(format!("no map found in '{}'", dir.to_string_lossy())),
-		_ => Ok(ls[0].clone()),
-	}
+	Campaign::load(dir)?.first()
 }
 
-/// List all maps (json files) in directory dir, alphabetically.
-fn ls_maps(dir: &Path) -> Result<Vec<PathBuf>> {
+/// List all maps (json or txt files) in directory dir, alphabetically.
+/// Includes maps that are not part of the campaign.
+pub fn ls_maps(dir: &Path) -> Result<Vec<PathBuf>> {
+	if !dir.is_dir() {
+		return Err(Error::NoMapDir(dir.to_owned()));
+	}
 	let mut ls: Vec<PathBuf> = fs::read_dir(dir)?
 		.map(|x| x.unwrap().path())
-		.filter(|x| x.extension() == Some(&OsStr::new("json")))
+		.filter(|x| x.extension() == Some(&OsStr::new("json")) || is_text(x))
+		.filter(|x| x.file_name() != Some(OsStr::new(CAMPAIGN_FILE)))
 		.collect();
 	ls.sort();
 	Ok(ls)
 }
+
+/// File name of a map, without its directory, e.g. "01.json". Identifies maps in campaigns and progress.
+pub fn file_name(p: &Path) -> String {
+	p.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Save map to a temporary file with the given extension, and load it back.
+	fn save_and_load(map: &Map, name: &str, ext: &str) -> Map {
+		let f = std::env::temp_dir().join(format!("game-test-{}-{}.{}", std::process::id(), name, ext));
+		save(map, &f).unwrap();
+		let loaded = load_unchecked(&f).unwrap();
+		fs::remove_file(&f).unwrap();
+		loaded
+	}
+
+	// Save map to a temporary file with the given extension, and report why it cannot be loaded for playing.
+	fn load_error(map: &Map, name: &str, ext: &str) -> String {
+		let f = std::env::temp_dir().join(format!("game-test-{}-{}.{}", std::process::id(), name, ext));
+		save(map, &f).unwrap();
+		let result = load(&f);
+		fs::remove_file(&f).unwrap();
+		match result {
+			Ok(_) => panic!("{}: loaded", name),
+			Err(e) => e.report(),
+		}
+	}
+
+	#[test]
+	fn round_trip_shipped_maps() {
+		let maps = ls_maps(Path::new("assets/maps")).unwrap();
+		assert!(!maps.is_empty());
+		for f in maps {
+			let map = load(&f).unwrap();
+			let name = f.file_stem().unwrap().to_string_lossy();
+			for ext in &["json", "txt"] {
+				let loaded = save_and_load(&map, &name, ext);
+				assert!(loaded == map, "{}: round trip through {}", f.to_string_lossy(), ext);
+				assert_eq!(loaded.checksum(), map.checksum());
+			}
+		}
+	}
+
+	#[test]
+	fn reject_unplayable_maps() {
+		let mut map = ascii::parse("#PP E#\n").unwrap();
+		let err = load_error(&map, "players", "txt");
+		assert!(err.ends_with("2 players, at row 0, column 1; row 0, column 2"));
+
+		map.layers[OBJECTS].set(Pt(2, 0), NONE);
+		map.set(Pt(3, 0), 99);
+		let err = load_error(&map, "unknown", "json");
+		assert!(err.ends_with("row 0, column 3: unknown block 99 (beyond NUM_BLOCKS = 56)"));
+		assert!(save_and_load(&map, "unknown", "json").has(Pt(3, 0), 99)); // the editor can still open it
+	}
+
+	#[test]
+	fn reject_bad_runs() {
+		let decode = |row: &str, size| decode_rows(&[row.to_string()], (0, 0), size, |_, _| ());
+		assert!(decode("1*3 0 2", Some((5, 1))).is_ok());
+		assert!(decode("1*0", Some((5, 1))).is_err());
+		assert!(decode("1*-2", Some((5, 1))).is_err());
+		assert!(decode("1*3 0 2*2", Some((5, 1))).is_err()); // wider than size
+		assert!(decode("1*2000000000", None).is_err()); // fails before expanding
+	}
+}
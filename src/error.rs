@@ -1,27 +1,88 @@
+use crate::validate::Problem;
 use std::error;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::result;
 
-pub type Error = Box<dyn error::Error>;
-
 pub type Result<T> = std::result::Result<T, Error>;
 
-// Generic error. Only has an error message.
+/// Everything that can go wrong.
+/// Specific enough for callers to react to, e.g. offer to create a missing map directory.
 #[derive(Debug)]
-pub struct GenError {
-	inner: String,
+pub enum Error {
+	Io(io::Error),             // reading or writing files (transparent)
+	Json(serde_json::Error),   // malformed JSON: map, replay, progress (transparent)
+	Parse(String),             // malformed map, e.g. "line 3, column 4: unknown block 'Q'"
+	Invalid(Vec<Problem>),     // map cannot be played, see validate.rs
+	File(PathBuf, Box<Error>), // loading or saving this file failed
+	NoMapDir(PathBuf),         // map directory does not exist
+	NoMaps(PathBuf),           // map directory contains no maps
+	Locked(String),            // world (name) not unlocked yet, see campaign.rs
+	Texture(PathBuf, String),  // loading a texture (file or directory) failed
+	Sdl(String),               // initializing SDL (video, window, ...) failed
+	Other(String),             // anything else, e.g. wrong command line arguments
 }
 
-impl GenError {
-	pub fn new<T>(inner: String) -> Result<T> {
-		Result::Err(Box::new(GenError { inner }))
+impl Error {
+	/// Attach the file being loaded or saved.
+	pub fn in_file(self, p: &Path) -> Error {
+		Error::File(p.to_owned(), Box::new(self))
+	}
+
+	/// The error followed by all its causes, for showing to the user.
+	/// E.g. "assets/maps/01.json: row 3, column 4: unknown block 99".
+	pub fn report(&self) -> String {
+		let mut msg = self.to_string();
+		let mut source = error::Error::source(self);
+		while let Some(e) = source {
+			msg += &format!(": {}", e);
+			source = error::Error::source(e);
+		}
+		msg
 	}
 }
 
-impl fmt::Display for GenError {
+impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-		write!(f, "{}", self.inner)
+		match self {
+			Error::Io(e) => write!(f, "{}", e),
+			Error::Json(e) => write!(f, "{}", e),
+			Error::Parse(msg) => write!(f, "{}", msg),
+			Error::Invalid(problems) => {
+				let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+				write!(f, "{}", problems.join("; "))
+			}
+			Error::File(p, _) => write!(f, "{}", p.to_string_lossy()),
+			Error::NoMapDir(p) => write!(f, "map directory '{}' does not exist", p.to_string_lossy()),
+			Error::NoMaps(p) => write!(f, "no map found in '{}'", p.to_string_lossy()),
+			Error::Locked(world) => write!(f, "world '{}' is locked", world),
+			Error::Texture(p, msg) => write!(f, "texture {}: {}", p.to_string_lossy(), msg),
+			Error::Sdl(msg) => write!(f, "SDL: {}", msg),
+			Error::Other(msg) => write!(f, "{}", msg),
+		}
+	}
+}
+
+impl error::Error for Error {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match self {
+			Error::Io(e) => error::Error::source(e),
+			Error::Json(e) => error::Error::source(e),
+			Error::File(_, e) => Some(e.as_ref()),
+			_ => None,
+		}
 	}
 }
 
-impl error::Error for GenError {}
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Error {
+		Error::Io(e)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(e: serde_json::Error) -> Error {
+		Error::Json(e)
+	}
+}
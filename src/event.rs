@@ -0,0 +1,16 @@
+use crate::prelude::*;
+
+/// Something noteworthy that happened during play, as reported by Gamestate::tick.
+/// Allows sound, HUD, statistics, tests, ... to react to gameplay.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+	KeyPickup(Pt, Block),    // key at grid position was picked up, removing locks
+	ButtonToggle(Pt, Block), // button at grid position was pressed, flipping toggles
+	Plate(Pt, bool),         // pressure plate at grid position was pressed (true) or released, flipping toggles while held
+	Sink(Pt),                // crate sank into the water at grid position, making it floor
+	Freeze(Pt),              // icecube turned the water at grid position into ice
+	Teleport(Pt, Pt),        // mover was teleported from, to grid position
+	Message(Pt),             // player entered a cell with a message, see Gamestate::message
+	Death(Death),            // player died
+	Exit,                    // map completed: player reached the exit, or all goals hold a crate
+}
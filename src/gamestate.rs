@@ -1,72 +1,293 @@
 use crate::encoding;
 use crate::prelude::*;
-use std::io;
-use std::io::Write;
 use std::iter::Iterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+// Ticks per second.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+// After dying, the map restarts after this many ticks.
+const DEATH_TICKS: u32 = 60;
+
+// Holding the undo key longer than this many ticks starts rewinding.
+const REWIND_DELAY: u32 = 15;
+
+// While rewinding, step back one move every this many ticks.
+const REWIND_INTERVAL: u32 = 4;
+
+// Action radius of keys and buttons, in blocks, unless set by the cell's properties.
+const ACTION_RADIUS: i32 = 32;
 
 /// Playable game state.
 pub struct Gamestate {
 	file: PathBuf,
-	pub map: Map,       // blocks with fixed location
-	pub mv: Vec<Mover>, // blocks with moving location, incl. player
+	pub map: Map,               // blocks with fixed location
+	pub mv: Vec<Mover>,         // blocks with moving location, incl. player
+	history: History,           // snapshots for undo, taken when the player becomes grid-aligned
+	undo_held: u32,             // number of ticks the undo key has been held down
+	replay: Replay,             // all input so far, for reproducing this run
+	map_id: MapId,              // identifies the map, for the player's progress
+	campaign: Option<Campaign>, // of the map's directory, loaded on first use and kept while playing its maps
+	status: Status,             // playing, dead, ...
+	status_ticks: u32,          // number of ticks since the status last changed
+	events: Vec<GameEvent>,     // events during the current tick
+	stats: Stats,               // moves, pushes, time spent
+	message: String,            // message of the last cell the player entered that had one, or of a locked world
+	plates: Vec<Plate>,         // all pressure plates on the map
+	receivers: Vec<Receiver>,   // all cells driven by pressure plates
+}
+
+// A pressure plate and whether it is held down by a mover.
+#[derive(Copy, Clone)]
+struct Plate {
+	pos: Pt, // grid position
+	blk: Block,
+	held: bool,
+}
+
+// A cell driven by pressure plates: signaled (see block_signaled) when the first of its plates is pressed,
+// and again when the last one is released.
+struct Receiver {
+	pos: Pt,            // grid position
+	plates: Vec<usize>, // indices of the plates driving this cell
+	held: bool,         // is any of the plates held down?
+}
+
+/// What is going on in the game.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Status {
+	Playing,
+	Dead(Death), // the map restarts shortly
+	Complete,    // reached the exit (or covered all goals), waiting for confirmation to go to the next map
+	Finished,    // completed the last map
+}
+
+/// How well the player is doing in the current attempt at a map.
+/// Starts over when the map is restarted (or the player dies).
+/// Undo takes back moves and pushes, but not time.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+	pub moves: u32,  // number of grid cells entered by the player
+	pub pushes: u32, // number of times the player pushed a crate or icecube
+	pub ticks: u32,  // time spent playing
+}
+
+/// How the player died.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Death {
+	Pig,     // touched a pig
+	Burned,  // walked into lava
+	Drowned, // walked into a whirlpool
 }
 
 impl Gamestate {
 	/// new empty gamestate.
 	pub fn empty() -> Gamestate {
-		Gamestate {
-			file: PathBuf::new(),
-			map: Map::new(),
-			mv: vec![],
-		}
+		Gamestate::new(PathBuf::new(), &Map::new())
+	}
+
+	fn new(file: PathBuf, staging: &Map) -> Gamestate {
+		let (map, mv) = encoding::unstage(staging);
+		let plates: Vec<Plate> = map
+			.blocks()
+			.into_iter()
+			.filter(|&(_, blk)| (PLATE_B..=PLATE_Y).contains(&blk))
+			.map(|(pos, blk)| Plate { pos, blk, held: false })
+			.collect();
+		let receivers = plate_receivers(&map, &plates);
+		let replay = Replay::new(file.clone(), staging);
+		let mut g = Gamestate {
+			map_id: MapId::new(&file, replay.hash),
+			campaign: None,
+			replay,
+			file,
+			history: History::new(Snapshot {
+				map: map.clone(),
+				mv: mv.clone(),
+				stats: Stats::default(),
+			}),
+			map,
+			mv,
+			undo_held: 0,
+			status: Status::Playing,
+			status_ticks: 0,
+			events: Vec::new(),
+			stats: Stats::default(),
+			message: String::new(),
+			plates,
+			receivers,
+		};
+
+		// the map shows toggles as they are with all plates released,
+		// so movers that start on a plate press it right away.
+		g.update_plates();
+		g.events.clear();
+		g.history = History::new(g.snapshot());
+		g
+	}
+
+	/// Construct a gamestate from an in-memory map (with movers still staged as blocks).
+	/// Does not touch the filesystem, so reaching the exit does not load a next map.
+	/// Used for headless simulation, e.g. mechanics tests and tools.
+	pub fn from_map(staging: &Map) -> Gamestate {
+		Gamestate::new(PathBuf::new(), staging)
 	}
 
 	/// Load a map from file (JSON).
 	pub fn load(file: PathBuf) -> Result<Gamestate> {
 		let staging = encoding::load(&file)?;
-		let (map, mv) = encoding::unstage(&staging);
-		Ok(Gamestate { file, map, mv })
+		Ok(Gamestate::new(file, &staging))
+	}
+
+	/// Headless driver: advance time by one tick per scripted key state.
+	/// Returns all events that happened along the way.
+	///
+	///     use game::prelude::*;
+	///     let mut staging = Map::new();
+	///     staging.set(Pt(1, 1), PLAYER);
+	///     staging.set(Pt(3, 1), EXIT);
+	///     let mut g = Gamestate::from_map(&staging);
+	///     let right = KeyStates::new().with_down(Key::Right);
+	///     g.run(std::iter::repeat(right).take(2 * (GRID / PLAYER_SPEED_) as usize));
+	///     assert_eq!(g.player().grid(), Pt(3, 1));
+	///     assert!(g.at_exit());
+	///
+	pub fn run<I: IntoIterator<Item = KeyStates>>(&mut self, script: I) -> Vec<GameEvent> {
+		let mut events = Vec::new();
+		for keys in script {
+			events.extend(self.tick(keys));
+		}
+		events
 	}
 
 	/// Handle non-debounced keys (i.e. not for time-critical stuff,
 	/// time-critical keys are handled in tick()).
-	pub fn handle_key(&mut self, k: Key) {
+	/// With progress, the next/previous map keys only go to unlocked worlds.
+	pub fn handle_key(&mut self, k: Key, progress: Option<&Progress>) {
 		match k {
-			Key::NextMap => self.next_map(1),
-			Key::PrevMap => self.next_map(-1),
+			Key::NextMap => self.next_map(1, progress),
+			Key::PrevMap => self.next_map(-1, progress),
 			Key::Restart => self.restart_map(),
+			Key::Save => self.try_save_replay(),
+			Key::A => self.confirm(progress),
 			_ => (),
 		}
 	}
 
+	// Move on to the next map after completing one.
+	fn confirm(&mut self, progress: Option<&Progress>) {
+		if self.status == Status::Complete && !self.in_memory() {
+			self.next_map(1, progress);
+		}
+	}
+
+	/// Identifies the map being played (see Progress).
+	pub fn map_id(&self) -> &MapId {
+		&self.map_id
+	}
+
+	/// All input recorded since the map was loaded.
+	pub fn replay(&self) -> &Replay {
+		&self.replay
+	}
+
+	// save the replay next to the map file, e.g. "maps/01.replay".
+	fn try_save_replay(&self) {
+		if self.in_memory() {
+			return;
+		}
+		let f = self.file.with_extension("replay");
+		if let Err(e) = self.replay.save(&f) {
+			eprintln!("Error saving replay to {}: {}", &f.to_string_lossy(), e.report());
+		}
+	}
+
 	fn restart_map(&mut self) {
 		self.try_load_map(self.file.clone());
 	}
 
-	/// Jump to the next or previous map,
+	/// Jump to the next or previous map in the campaign,
 	/// for delta 1 or -1, respectively.
-	fn next_map(&mut self, delta: i32) {
-		match encoding::find_next_map(&self.file, delta) {
-			Ok(f) => self.try_load_map(f),
-			Err(e) => writeln!(io::stderr(), "{}", e).unwrap(),
+	// Completing the last map finishes the game.
+	// Locked worlds are reported to the player through message().
+	fn next_map(&mut self, delta: i32, progress: Option<&Progress>) {
+		if self.campaign.is_none() {
+			match encoding::campaign_of(&self.file) {
+				Ok(c) => self.campaign = Some(c),
+				Err(e) => return eprintln!("{}", e.report()),
+			}
+		}
+		match self.campaign.as_ref().unwrap().next(&self.file, delta, progress) {
+			Ok(Some(f)) => self.try_load_map(f),
+			Ok(None) if self.status == Status::Complete => self.set_status(Status::Finished),
+			Ok(None) => (),
+			Err(Error::Locked(world)) => self.message = format!("world '{}' is locked, complete more maps first", world),
+			Err(e) => eprintln!("{}", e.report()),
 		}
 	}
 
+	// Load another map of the same campaign (or the same map again).
 	fn try_load_map(&mut self, f: PathBuf) {
 		match Gamestate::load(f) {
-			Ok(g) => *self = g,
-			Err(e) => writeln!(io::stderr(), "{}", e).unwrap(),
+			Ok(g) => {
+				*self = Gamestate {
+					campaign: self.campaign.take(),
+					..g
+				}
+			}
+			Err(e) => eprintln!("{}", e.report()),
 		}
 	}
 
 	/// Advance time by one tick (1/60th of a second).
-	/// Player moves according to currently pressed keys.
-	pub fn tick(&mut self, keys: KeyStates) {
+	/// Player moves according to currently pressed keys, which are recorded in the replay
+	/// (saved with the Save key, see handle_key).
+	/// Returns what happened during the tick (keys picked up, ...).
+	pub fn tick(&mut self, keys: KeyStates) -> Vec<GameEvent> {
+		self.replay.record(keys);
+		self.step(keys)
+	}
+
+	/// Like tick, but without recording the input in the replay.
+	/// Used by the solver, which plays many alternative moves from the same states,
+	/// and when playing back a replay.
+	pub fn step(&mut self, keys: KeyStates) -> Vec<GameEvent> {
+		self.advance(keys);
+		std::mem::take(&mut self.events)
+	}
+
+	fn advance(&mut self, keys: KeyStates) {
+		// undo freezes time. Pressing undo steps back one move,
+		// holding it down keeps rewinding.
+		if keys.down[Key::Undo.id()] {
+			let held = self.undo_held;
+			if held == 0 || (held >= REWIND_DELAY && (held - REWIND_DELAY).is_multiple_of(REWIND_INTERVAL)) {
+				self.undo();
+			}
+			self.undo_held += 1;
+			return;
+		}
+		self.undo_held = 0;
+
+		// dead players don't move. restart after a little while.
+		// after completing the map, wait for confirmation (see confirm()).
+		self.status_ticks += 1;
+		match self.status {
+			Status::Playing => (),
+			Status::Dead(_) => {
+				if self.status_ticks >= DEATH_TICKS {
+					self.restore(self.history.first().clone());
+				}
+				return;
+			}
+			Status::Complete | Status::Finished => return,
+		}
+		self.stats.ticks += 1;
+
 		// set player move intent according to keys pressed
 		self.mv[0].intent = player_move_intent(keys, self.player().pos, self);
-		let under = (self.player().pos + Pt(GRID / 2, GRID / 2)) / GRID;
-		if self.map[under] != ICE && self.can_move(0, self.player().intent, self.player().speed()) {
+		let under = self.player().approx_grid();
+		if !self.map.has(under, ICE) && self.can_move(0, self.player().intent, self.player().speed()) {
 			self.try_set_inertia(0, self.mv[0].intent);
 		}
 
@@ -76,8 +297,11 @@ impl Gamestate {
 			// we can only change inertia if aligned to grid,
 			// otherwise we could move off-grid (chaos ensured).
 			if self.mv[i].aligned() {
+				if self.mv[i].typ() == PIG {
+					steer_pig(self, i);
+				}
 				let intent = self.mv[i].move_intent();
-				let blk = self.map[self.mv[i].pos().grid()];
+				let blk = self.steering_block(self.mv[i].pos().grid());
 				self.try_set_inertia(
 					i,
 					match blk {
@@ -100,14 +324,90 @@ impl Gamestate {
 		// triggers may have killed movers, prune them.
 		self.prune_killed_movers();
 
-		// finish level should be handled separately, not in the middle of the movers loop
-		if self.player().aligned() {
-			if self.map[self.player().grid()] == EXIT {
-				self.next_map(1);
-			}
+		// pressure plates follow whoever stands on them.
+		self.update_plates();
+
+		// remember each grid position the player reaches, for undo.
+		if self.player().aligned() && self.player().pos != self.history.last().mv[0].pos {
+			self.history.push(self.snapshot());
+		}
+	}
+
+	/// Step back to the previous grid-aligned position of the player.
+	/// Stops at the starting position.
+	pub fn undo(&mut self) {
+		// if the player has left the last snapshot position, go back there.
+		// if still standing there, go back one more.
+		if self.player().pos == self.history.last().mv[0].pos {
+			self.history.pop();
+		}
+		let s = self.history.last().clone();
+		self.map = s.map;
+		self.mv = s.mv;
+		self.stats = Stats {
+			ticks: self.stats.ticks, // undo does not turn back time
+			..s.stats
+		};
+		self.reset_plates();
+		self.set_status(Status::Playing);
+	}
+
+	/// Copy of the current map and movers.
+	pub fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			map: self.map.clone(),
+			mv: self.mv.clone(),
+			stats: self.stats,
 		}
 	}
 
+	/// Continue playing from a snapshot.
+	/// Undo history starts over from there.
+	pub fn restore(&mut self, s: Snapshot) {
+		self.history = History::new(s.clone());
+		self.map = s.map;
+		self.mv = s.mv;
+		self.stats = s.stats;
+		self.reset_plates();
+		self.set_status(Status::Playing);
+	}
+
+	/// What is going on: playing, dead, ...
+	pub fn status(&self) -> Status {
+		self.status
+	}
+
+	/// Moves, pushes and time spent so far in this attempt.
+	pub fn stats(&self) -> Stats {
+		self.stats
+	}
+
+	/// Message of the last cell (with a message) the player entered, if any. See Props.
+	/// Or why the next map cannot be played (its world is locked).
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
+	/// The map file being played (empty if constructed from_map).
+	pub fn file(&self) -> &Path {
+		&self.file
+	}
+
+	fn set_status(&mut self, status: Status) {
+		self.status = status;
+		self.status_ticks = 0;
+	}
+
+	/// Is the player standing on the exit?
+	pub fn at_exit(&self) -> bool {
+		self.player().aligned() && self.map.has(self.player().grid(), EXIT)
+	}
+
+	// constructed from_map, not backed by a file.
+	fn in_memory(&self) -> bool {
+		self.file.as_os_str().is_empty()
+	}
+
 	fn try_set_inertia(&mut self, i: usize, dir: Dir) {
 		if dir == Dir::None {
 			if self.mv[i].aligned() {
@@ -116,8 +416,8 @@ impl Gamestate {
 			return;
 		}
 
-		let under = self.map[self.mv[i].approx_grid()];
-		if alignment_allows_move(self.mv[i].pos, dir) && block_arrow_dir(under) != dir.opposite() {
+		let under = self.mv[i].approx_grid();
+		if alignment_allows_move(self.mv[i].pos, dir) && self.map.arrow_dir(under) != dir.opposite() {
 			self.mv[i].inertia_ = dir;
 		}
 
@@ -148,6 +448,7 @@ impl Gamestate {
 		if can_move {
 			self.do_move(i, dir, amount);
 		} else {
+			self.process_bump_pig(i, dir, amount);
 			self.mv[i].on_bump();
 
 			// bump while not aligned can be problematic,
@@ -162,7 +463,7 @@ impl Gamestate {
 			}
 
 			// bounce back if bumping while on ice
-			if !can_move && self.map[self.mv[i].approx_grid()] == ICE {
+			if !can_move && self.map.has(self.mv[i].approx_grid(), ICE) {
 				self.try_set_inertia(i, self.mv[i].inertia().opposite());
 			}
 		}
@@ -174,33 +475,61 @@ impl Gamestate {
 		self.mv[i].inertia_ = dir;
 		self.mv[i].pos += dir.vector() * amount;
 		if self.mv[i].pos.aligned() {
+			if i == 0 {
+				self.stats.moves += 1;
+			}
 			self.process_triggers(i);
 			self.mv[i].on_align();
 		}
 	}
 
 	// player bumps into other Mover.
-	// if it's a crate or icecube, will try to push it forward.
+	// if it's a crate or icecube, will try to push it forward,
+	// together with the crates or icecubes in front of it, up to the map's push limit (see Rules).
 	fn process_bump_player(&mut self, dir: Dir, amount: i32) {
 		let i = 0; // player
 
-		// which mover did player bump into?
+		// which movers did player bump into?
+		// a row of crates or icecubes touching each other, up to the push limit,
+		// or a single other mover (e.g. a pig).
+		let mut row = Vec::new();
+		let mut probe = probe_point(self.mv[i].pos, dir, amount);
+		while let Some(j) = self.mover_at(probe) {
+			if !self.mv[j].is_pushable() {
+				if row.is_empty() {
+					row.push(j);
+				}
+				break;
+			}
+			if row.len() == self.map.rules.push as usize || row.contains(&j) {
+				break;
+			}
+			row.push(j);
+			probe = probe_point(self.mv[j].pos, dir, amount);
+		}
+
+		// push the row front to back, so that each mover makes room for the one behind it.
 		// call it's on_bump, which may cause it wanting to move.
 		// if it wants to move in the bump direction, move it right away,
 		// so that it stops blocking the player.
-		let probe_point = probe_point(self.mv[i].pos, dir, amount);
-		if let Some(j) = self.mover_at(probe_point) {
+		let mut pushed = false;
+		for &j in row.iter().rev() {
 			if self.mv[j].aligned() {
 				self.mv[j].on_bumped(dir); // crates set move_intent on bump
 			}
 
 			let intent = self.mv[j].move_intent();
 			if intent == dir {
+				let before = self.mv[j].pos;
 				self.try_set_inertia(j, intent);
 				self.try_coast(j);
 				self.try_coast(j); // hack for ice+crate, so that player does not bounced back when on ice.
+				pushed |= self.mv[j].pos != before;
 			}
 		}
+		if pushed {
+			self.stats.pushes += 1;
+		}
 
 		// try to move the player again, now the the obstruction
 		// has potentially been pushed out of the way.
@@ -209,6 +538,24 @@ impl Gamestate {
 		}
 	}
 
+	// Mover i bumps into something while moving.
+	// If the player and a pig bump into each other, the player dies.
+	fn process_bump_pig(&mut self, i: usize, dir: Dir, amount: i32) {
+		if let Some(j) = self.obstacle(i, dir, amount) {
+			match (self.mv[i].typ(), self.mv[j].typ()) {
+				(PLAYER, PIG) | (PIG, PLAYER) => self.die(Death::Pig),
+				_ => (),
+			}
+		}
+	}
+
+	fn die(&mut self, cause: Death) {
+		if self.status == Status::Playing {
+			self.set_status(Status::Dead(cause));
+			self.events.push(GameEvent::Death(cause));
+		}
+	}
+
 	// Process triggers for mover i, which must be aligned to grid.
 	// This triggers keys, buttons, ...  and causes crates on water to turn into floor.
 	fn process_triggers(&mut self, i: usize) {
@@ -221,44 +568,137 @@ impl Gamestate {
 		}
 
 		let grid = self.mv[i].grid();
-		let blk = self.map[grid];
+		let typ = self.mv[i].typ();
 
-		// effect on map (locks, keys, ...)
-		match self.map[grid] {
-			KEY_B..=KEY_Y => self.trigger_key(grid),
-			BUTTON_B..=BUTTON_Y => self.trigger_button(grid),
-			_ => (),
+		// messages for the player
+		if typ == PLAYER && !self.map.props(grid).message.is_empty() {
+			self.message = self.map.props(grid).message.clone();
+			self.events.push(GameEvent::Message(grid));
 		}
 
-		// effect on movers (crates, ice)
-		let typ = self.mv[i].typ();
-		match (typ, blk) {
-			(CRATE, WATER) => {
-				self.map.set(grid, NONE);
-				self.mv[i].kill();
+		// each layer triggers, bottom to top.
+		for l in 0..NUM_LAYERS {
+			let blk = self.map.layers[l][grid];
+
+			// effect on map (locks, keys, ...)
+			match blk {
+				KEY_B..=KEY_Y => self.trigger_key(l, grid),
+				BUTTON_B..=BUTTON_Y => self.trigger_button(l, grid),
+				_ => (),
 			}
-			(ICECUBE, WATER) => {
-				self.map.set(grid, ICE);
-				self.mv[i].kill();
+
+			// effect on movers (crates, ice, player)
+			match (typ, blk) {
+				(PLAYER, EXIT) => self.complete(),
+				(CRATE, GOAL) if self.all_goals_covered() => self.complete(),
+				(PLAYER, LAVA) => self.die(Death::Burned),
+				(PLAYER, WHIRLPOOL) => self.die(Death::Drowned),
+				(_, TELEPORTER) => self.teleport(i, grid),
+				(CRATE, WATER) => {
+					self.map.layers[l].set(grid, NONE);
+					self.mv[i].kill();
+					self.events.push(GameEvent::Sink(grid));
+				}
+				(ICECUBE, WATER) => {
+					self.map.layers[l].set(grid, ICE);
+					self.mv[i].kill();
+					self.events.push(GameEvent::Freeze(grid));
+				}
+				_ => (),
 			}
-			_ => (),
 		}
 	}
 
-	/// Trigger the key at grid postion pos.
-	/// Removes all locks of the same color (inside the action radius).
-	fn trigger_key(&mut self, pos: Pt) {
-		let lock = self.map[pos] - 4; // lock corresponding to this key. see blocks.rs.
-		self.map.set(pos, NONE); // remove key
-		self.map.replace(Gamestate::action_range(pos), |b| if b == lock { NONE } else { b });
+	// The block that steers movers at grid position p: an arrow (top-most layer first),
+	// otherwise ICE, otherwise NONE.
+	fn steering_block(&self, p: Pt) -> Block {
+		let cell = self.map.at(p);
+		match cell.iter().rev().find(|&&b| block_is_arrow(b)) {
+			Some(&arrow) => arrow,
+			None if cell.contains(&ICE) => ICE,
+			None => NONE,
+		}
+	}
+
+	// Send mover i from the teleporter at grid position from to its partner, keeping its inertia.
+	// Nothing happens if the partner is missing, occupied, or cannot be entered by the mover.
+	fn teleport(&mut self, i: usize, from: Pt) {
+		let to = match self.map.props(from).target {
+			Some(to) if self.map.has(to, TELEPORTER) => to,
+			_ => return,
+		};
+		if !self.mv[i].can_walk(self.map.at(to)) {
+			return;
+		}
+		let dest = Rect::new(to * GRID, GRID, GRID);
+		if (0..self.mv.len()).any(|j| j != i && self.mv[j].rect().overlaps(&dest)) {
+			return;
+		}
+		self.mv[i].pos = to * GRID;
+		self.events.push(GameEvent::Teleport(from, to));
 	}
 
-	/// Trigger the button at grid postion pos.
-	/// Toggles the corresponding toggle blocks (inside the action radius).
-	fn trigger_button(&mut self, pos: Pt) {
-		let open = self.map[pos] - 4; // block corresponding to this button. see blocks.rs.
-		let close = self.map[pos] - 8; // block corresponding to this button. see blocks.rs.
-		self.map.replace(Gamestate::action_range(pos), |b| {
+	// Only once, and not after dying in the same tick.
+	fn complete(&mut self) {
+		if self.status == Status::Playing {
+			self.set_status(Status::Complete);
+			self.message.clear(); // free for the next map's status, see next_map
+			self.events.push(GameEvent::Exit);
+		}
+	}
+
+	// Sokoban-style win condition: every goal holds a crate (at rest).
+	fn all_goals_covered(&self) -> bool {
+		self.map
+			.blocks()
+			.iter()
+			.filter(|&&(_, blk)| blk == GOAL)
+			.all(|&(grid, _)| self.mv.iter().any(|m| m.typ() == CRATE && m.aligned() && m.grid() == grid))
+	}
+
+	/// Trigger the key at grid postion pos, in layer l.
+	/// Signals the receivers it is wired to, if any.
+	/// Otherwise, removes all locks of the same color (inside the action radius).
+	fn trigger_key(&mut self, l: usize, pos: Pt) {
+		let key = self.map.layers[l][pos];
+		let lock = key - 4; // lock corresponding to this key. see blocks.rs.
+		self.events.push(GameEvent::KeyPickup(pos, key));
+		self.map.layers[l].set(pos, NONE); // remove key
+		if !self.signal(pos) {
+			let range = Gamestate::action_range(&self.map, pos);
+			self.map.replace(range, |b| if b == lock { NONE } else { b });
+		}
+	}
+
+	/// Trigger the button at grid postion pos, in layer l.
+	/// Signals the receivers it is wired to, if any.
+	/// Otherwise, toggles the corresponding toggle blocks (inside the action radius).
+	fn trigger_button(&mut self, l: usize, pos: Pt) {
+		let button = self.map.layers[l][pos];
+		let open = button - 4; // block corresponding to this button. see blocks.rs.
+		let close = button - 8; // block corresponding to this button. see blocks.rs.
+		self.events.push(GameEvent::ButtonToggle(pos, button));
+		if !self.signal(pos) {
+			self.flip_toggles(pos, open, close);
+		}
+	}
+
+	// Signal all receivers wired to the trigger at pos (see Map::connect).
+	// Returns false if the trigger is not wired.
+	fn signal(&mut self, pos: Pt) -> bool {
+		let receivers = self.map.wires_from(pos);
+		for &to in &receivers {
+			for layer in &mut self.map.layers {
+				layer.set(to, block_signaled(layer[to]));
+			}
+		}
+		!receivers.is_empty()
+	}
+
+	// Flip toggle blocks open <-> close, inside the action radius of pos.
+	fn flip_toggles(&mut self, pos: Pt, open: Block, close: Block) {
+		let range = Gamestate::action_range(&self.map, pos);
+		self.map.replace(range, |b| {
 			if b == open {
 				close
 			} else if b == close {
@@ -269,8 +709,62 @@ impl Gamestate {
 		});
 	}
 
-	fn action_range(center: Pt) -> (Pt, Pt) {
-		(center - Pt(32, 32), center + Pt(32, 32))
+	// Press pressure plates that a mover has stepped on, release those that have been vacated.
+	// A receiver is signaled only when it goes from no plate held to some plate held, or back,
+	// so that plates driving the same cell do not cancel each other.
+	// Signaling the current blocks keeps what buttons and keys did to the cell in the meantime.
+	fn update_plates(&mut self) {
+		for i in 0..self.plates.len() {
+			let Plate { pos, held, .. } = self.plates[i];
+			if self.is_occupied(pos) != held {
+				self.plates[i].held = !held;
+				self.events.push(GameEvent::Plate(pos, !held));
+			}
+		}
+		let plates = &self.plates;
+		for r in &mut self.receivers {
+			let held = r.plates.iter().any(|&i| plates[i].held);
+			if held != r.held {
+				r.held = held;
+				for layer in &mut self.map.layers {
+					layer.set(r.pos, block_signaled(layer[r.pos]));
+				}
+			}
+		}
+	}
+
+	// Re-evaluate which plates are held down, without touching the map.
+	// Used after restoring a snapshot, whose map already shows the receivers accordingly.
+	fn reset_plates(&mut self) {
+		for i in 0..self.plates.len() {
+			self.plates[i].held = self.is_occupied(self.plates[i].pos);
+		}
+		let plates = &self.plates;
+		for r in &mut self.receivers {
+			r.held = r.plates.iter().any(|&i| plates[i].held);
+		}
+	}
+
+	// Does any mover (partially) cover grid position pos?
+	fn is_occupied(&self, pos: Pt) -> bool {
+		let r = Rect::new(pos * GRID, GRID, GRID);
+		self.mv.iter().any(|m| m.rect().overlaps(&r))
+	}
+
+	/// Range of grid positions affected by the unwired key, button or plate at center.
+	/// The radius can be set per cell (see Props), ACTION_RADIUS by default, and is at most MAX_RADIUS.
+	pub fn action_range(map: &Map, center: Pt) -> (Pt, Pt) {
+		let r = map.props(center).radius.unwrap_or(ACTION_RADIUS).clamp(0, MAX_RADIUS);
+		let Pt(x, y) = center;
+		let min = Pt(x.saturating_sub(r), y.saturating_sub(r)); // saturate at the edge of the plane
+		let max = Pt(x.saturating_add(r), y.saturating_add(r));
+		(min, max)
+	}
+
+	// The mover (other than i) that would obstruct mover i moving in direction dir, if any.
+	fn obstacle(&self, i: usize, dir: Dir, amount: i32) -> Option<usize> {
+		let probe_rect = Rect::new(self.mv[i].pos + dir.vector() * amount, GRID, GRID);
+		(0..self.mv.len()).find(|&j| j != i && self.mv[j].rect().overlaps(&probe_rect))
 	}
 
 	fn mover_at(&self, pos: Pt) -> Option<usize> {
@@ -300,11 +794,11 @@ impl Gamestate {
 
 		// edge cannot move into something unwalkable
 		let pp = probe_point(pos, dir, amount);
-		let blk = self.map[pp / GRID]; // probe pos not neccesarily aligned
-		if !self.mv[i].can_walk(blk) {
+		let cell = pp.div_floor(GRID); // probe pos not neccesarily aligned
+		if !self.mv[i].can_walk(self.map.at(cell)) {
 			return false;
 		}
-		if dir == block_arrow_dir(blk).opposite() {
+		if dir == self.map.arrow_dir(cell).opposite() {
 			return false;
 		}
 
@@ -372,3 +866,302 @@ pub fn player_move_intent(keys: KeyStates, pos: Pt, g: &Gamestate) -> Dir {
 	}
 	intent
 }
+
+// The cells driven by the plates: the receivers a plate is wired to (see Map::connect),
+// or, for an unwired plate, the toggles of its color inside its action radius.
+fn plate_receivers(map: &Map, plates: &[Plate]) -> Vec<Receiver> {
+	let mut receivers: Vec<Receiver> = Vec::new();
+	for (i, plate) in plates.iter().enumerate() {
+		let mut targets = map.wires_from(plate.pos);
+		if targets.is_empty() {
+			let color = plate.blk - PLATE_B; // see blocks.rs
+			let toggles = [TOGGLE_OPEN_B + color, TOGGLE_CLOSED_B + color];
+			let (min, max) = Gamestate::action_range(map, plate.pos);
+			targets = map
+				.blocks()
+				.into_iter()
+				.filter(|&(p, b)| toggles.contains(&b) && p.0 >= min.0 && p.0 < max.0 && p.1 >= min.1 && p.1 < max.1)
+				.map(|(p, _)| p)
+				.collect();
+		}
+		for pos in targets {
+			match receivers.iter_mut().find(|r| r.pos == pos) {
+				Some(r) => r.plates.push(i),
+				None => receivers.push(Receiver {
+					pos,
+					plates: vec![i],
+					held: false,
+				}),
+			}
+		}
+	}
+	receivers
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ascii;
+
+	// Ticks for the player to walk one cell.
+	const CELL: usize = (GRID / PLAYER_SPEED_) as usize;
+
+	// Gamestate for a map in text format.
+	fn game(text: &str) -> Gamestate {
+		Gamestate::from_map(&ascii::parse(text).unwrap())
+	}
+
+	// Hold down key k for as long as it takes to walk n cells.
+	fn walk(g: &mut Gamestate, k: Key, n: usize) -> Vec<GameEvent> {
+		g.run(std::iter::repeat_n(KeyStates::new().with_down(k), n * CELL))
+	}
+
+	#[test]
+	fn walk_to_exit() {
+		let mut g = game("#P  E#\n");
+		walk(&mut g, Key::Right, 2);
+		assert_eq!(g.player().grid(), Pt(3, 0));
+		assert_eq!(g.stats().moves, 2);
+		assert_eq!(g.status(), Status::Playing);
+
+		let events = walk(&mut g, Key::Right, 1);
+		assert!(events.contains(&GameEvent::Exit));
+		assert_eq!(g.status(), Status::Complete);
+		assert!(g.at_exit());
+	}
+
+	#[test]
+	fn bricks_block() {
+		let mut g = game("#P#E\n");
+		walk(&mut g, Key::Right, 3);
+		assert_eq!(g.player().grid(), Pt(1, 0));
+		assert_eq!(g.stats().moves, 0);
+	}
+
+	#[test]
+	fn negative_coordinates() {
+		// the button opens the door, both left of and above the origin
+		let mut g = game("; origin: -5 -3\n#P i 1E#\n");
+		walk(&mut g, Key::Right, 2);
+		assert_eq!(g.player().grid(), Pt(-2, -3));
+		assert!(g.map.has(Pt(0, -3), TOGGLE_OPEN_B));
+		walk(&mut g, Key::Right, 3);
+		assert_eq!(g.status(), Status::Complete);
+	}
+
+	#[test]
+	fn undo_restores_stats_and_plates() {
+		// pushing the crate onto the plate opens the door
+		let mut g = game("#PXm 1 E#\n");
+		walk(&mut g, Key::Right, 1);
+		assert_eq!(g.stats().pushes, 1);
+		assert!(g.map.has(Pt(5, 0), TOGGLE_OPEN_B));
+
+		g.undo();
+		assert_eq!(g.player().grid(), Pt(1, 0));
+		assert_eq!(g.stats().moves, 0);
+		assert_eq!(g.stats().pushes, 0);
+		assert!(g.map.has(Pt(5, 0), TOGGLE_CLOSED_B));
+
+		// the plate is released after undo, so pushing again presses it again
+		walk(&mut g, Key::Right, 1);
+		assert!(g.map.has(Pt(5, 0), TOGGLE_OPEN_B));
+	}
+
+	#[test]
+	fn rewind() {
+		let mut g = game("#P   E#\n");
+		walk(&mut g, Key::Right, 3);
+		assert_eq!(g.stats().moves, 3);
+
+		// holding undo steps back once right away, then once every REWIND_INTERVAL after REWIND_DELAY
+		let ticks = (REWIND_DELAY + 2 * REWIND_INTERVAL) as usize;
+		g.run(std::iter::repeat_n(KeyStates::new().with_down(Key::Undo), ticks));
+		assert_eq!(g.player().grid(), Pt(1, 0));
+		assert_eq!(g.stats().moves, 0);
+		assert_eq!(g.stats().ticks, (3 * CELL) as u32); // undo does not turn back time
+	}
+
+	#[test]
+	fn death_and_restart() {
+		for (text, death) in &[("#P %E#\n", Death::Burned), ("#P @E#\n", Death::Drowned), ("#P  {#\n", Death::Pig)] {
+			let mut g = game(text);
+			let events = walk(&mut g, Key::Right, 2);
+			assert!(events.contains(&GameEvent::Death(*death)), "{}", text);
+			assert_eq!(g.status(), Status::Dead(*death));
+
+			// the map restarts after a while
+			g.run(std::iter::repeat_n(KeyStates::new(), DEATH_TICKS as usize));
+			assert_eq!(g.status(), Status::Playing);
+			assert_eq!(g.player().grid(), Pt(1, 0));
+			assert_eq!(g.stats().moves, 0);
+		}
+	}
+
+	#[test]
+	fn cover_all_goals() {
+		let mut g = game("#.XP X.#\n");
+		walk(&mut g, Key::Left, 1);
+		assert_eq!(g.status(), Status::Playing); // one goal is still empty
+
+		let events = walk(&mut g, Key::Right, 3);
+		assert_eq!(events.iter().filter(|&&e| e == GameEvent::Exit).count(), 1);
+		assert_eq!(g.status(), Status::Complete);
+		assert_eq!(g.stats().pushes, 2);
+	}
+
+	// Teleporters at 2 0 and 3 1, sending to each other.
+	const TELEPORTERS: &str = "; props: 2 0 target=3 1\n; props: 3 1 target=2 0\n";
+
+	#[test]
+	fn teleport() {
+		let mut g = game(&format!("#PT E#\n#  T #\n{}", TELEPORTERS));
+		let events = walk(&mut g, Key::Right, 1);
+		assert!(events.contains(&GameEvent::Teleport(Pt(2, 0), Pt(3, 1))));
+		assert_eq!(g.player().grid(), Pt(3, 1));
+	}
+
+	#[test]
+	fn teleport_blocked_when_occupied() {
+		let text = "; layer: floor\n#PT E#\n#  T #\n; layer: objects\n; origin: 3 1\nX\n";
+		let mut g = game(&format!("{}{}", text, TELEPORTERS));
+		let events = walk(&mut g, Key::Right, 1);
+		assert!(!events.iter().any(|e| matches!(e, GameEvent::Teleport(..))));
+		assert_eq!(g.player().grid(), Pt(2, 0));
+	}
+
+	#[test]
+	fn chain_push() {
+		// by default, only one crate at a time
+		let mut g = game("#PXX  E#\n");
+		walk(&mut g, Key::Right, 1);
+		assert_eq!(g.player().grid(), Pt(1, 0));
+		assert_eq!(g.stats().pushes, 0);
+
+		let mut g = game("; push: 2\n#PXX  E#\n");
+		walk(&mut g, Key::Right, 2);
+		assert_eq!(g.player().grid(), Pt(3, 0));
+		assert!(g.mv.iter().any(|m| m.grid() == Pt(4, 0)) && g.mv.iter().any(|m| m.grid() == Pt(5, 0)));
+		assert_eq!(g.stats().pushes, 2); // one per step, not per crate
+
+		let mut g = game("; push: 2\n#PXXX E#\n");
+		walk(&mut g, Key::Right, 1);
+		assert_eq!(g.player().grid(), Pt(1, 0));
+	}
+
+	#[test]
+	fn plates_do_not_cancel() {
+		// two plates hold the same door: it stays open while either is pressed
+		let mut g = game("#P m m  1E#\n");
+		for (x, open) in &[(2, false), (3, true), (4, false), (5, true), (6, false)] {
+			walk(&mut g, Key::Right, 1);
+			assert_eq!(g.player().grid(), Pt(*x, 0));
+			assert_eq!(g.map.has(Pt(8, 0), TOGGLE_OPEN_B), *open);
+		}
+
+		let mut g = game("; push: 2\n#PXXmm 1E#\n");
+		walk(&mut g, Key::Right, 2); // crates onto both plates
+		assert!(g.map.has(Pt(7, 0), TOGGLE_OPEN_B));
+	}
+
+	#[test]
+	fn plates_keep_button_changes() {
+		// the button opens the door, the plate closes it while held, releasing it opens it again
+		let mut g = game("#Pim   1E#\n");
+		for (x, open) in &[(2, true), (3, false), (4, true)] {
+			walk(&mut g, Key::Right, 1);
+			assert_eq!(g.player().grid(), Pt(*x, 0));
+			assert_eq!(g.map.has(Pt(7, 0), TOGGLE_OPEN_B), *open);
+		}
+	}
+
+	#[test]
+	fn button_radius() {
+		let mut g = game("#Pi 1   1E#\n; props: 2 0 radius=3\n");
+		let events = walk(&mut g, Key::Right, 1);
+		assert!(events.contains(&GameEvent::ButtonToggle(Pt(2, 0), BUTTON_B)));
+		assert!(g.map.has(Pt(4, 0), TOGGLE_OPEN_B));
+		assert!(g.map.has(Pt(8, 0), TOGGLE_CLOSED_B)); // out of range
+	}
+
+	#[test]
+	fn radius_is_bounded() {
+		assert!(ascii::parse("#Pi 1E#\n; props: 2 0 radius=2147483647\n").is_err());
+		assert!(ascii::parse("#Pi 1E#\n; props: 2 0 radius=-1\n").is_err());
+
+		// e.g. set in the editor or by a tool, bypassing the parser
+		let mut map = ascii::parse("#Pi 1E#\n").unwrap();
+		let props = Props {
+			radius: Some(i32::MAX),
+			..Props::default()
+		};
+		map.set_props(Pt(2, 0), props);
+		let r = MAX_RADIUS;
+		assert_eq!(Gamestate::action_range(&map, Pt(2, 0)), (Pt(2 - r, -r), Pt(2 + r, r)));
+	}
+
+	#[test]
+	fn wired_triggers() {
+		// a wired button only signals its receivers, however close other toggles are
+		let mut g = game("#Pi 1   1E#\n; wire: 2 0 8 0\n");
+		walk(&mut g, Key::Right, 1);
+		assert!(g.map.has(Pt(4, 0), TOGGLE_CLOSED_B));
+		assert!(g.map.has(Pt(8, 0), TOGGLE_OPEN_B));
+
+		// a wired plate holds its receivers only while pressed
+		let mut g = game("#Pm 1   1E#\n; wire: 2 0 8 0\n");
+		walk(&mut g, Key::Right, 1);
+		assert!(g.map.has(Pt(4, 0), TOGGLE_CLOSED_B));
+		assert!(g.map.has(Pt(8, 0), TOGGLE_OPEN_B));
+		walk(&mut g, Key::Left, 1);
+		assert!(g.map.has(Pt(8, 0), TOGGLE_CLOSED_B));
+	}
+
+	// Grid cells the pig (mover 1) passes through during the given number of ticks, without input.
+	fn pig_path(g: &mut Gamestate, ticks: usize) -> Vec<Pt> {
+		let mut path: Vec<Pt> = Vec::new();
+		for _ in 0..ticks {
+			g.tick(KeyStates::new());
+			let p = g.mv[1].approx_grid();
+			if path.last() != Some(&p) {
+				path.push(p);
+			}
+		}
+		path
+	}
+
+	#[test]
+	fn chasing_pig() {
+		let text = "#######\n#P    #\n# ### #\n#    V#\n#######\n";
+		let mut g = game(&format!("{}; props: 5 3 ai=chase\n", text));
+		let events = g.run(std::iter::repeat_n(KeyStates::new(), 20 * CELL));
+		assert!(events.contains(&GameEvent::Death(Death::Pig)));
+
+		// a patrolling pig keeps walking up and down its corridor
+		let mut g = game(text);
+		let events = g.run(std::iter::repeat_n(KeyStates::new(), 20 * CELL));
+		assert!(!events.contains(&GameEvent::Death(Death::Pig)));
+	}
+
+	#[test]
+	fn wall_following_pig() {
+		// the pig walks around the brick in the middle, keeping it on its right
+		let mut g = game("#######\n#}  #P#\n# # ###\n#   #\n#####\n; props: 1 1 ai=follow\n");
+		let path = pig_path(&mut g, 20 * CELL);
+		for x in 1..=3 {
+			for y in 1..=3 {
+				assert_eq!(path.contains(&Pt(x, y)), Pt(x, y) != Pt(2, 2), "{:?}", path);
+			}
+		}
+	}
+
+	#[test]
+	fn random_pig() {
+		let text = "#########\n#P#     #\n### # # #\n#   }   #\n# # # # #\n#       #\n#########\n";
+		let walk = |seed: u64| pig_path(&mut game(&format!("{}; props: 4 3 ai=random; seed={}\n", text, seed)), 100 * CELL);
+		let path = walk(7);
+		assert!(path.len() > 50);
+		assert!(path == walk(7)); // the same every time
+		assert!(path != walk(8));
+	}
+}
@@ -0,0 +1,51 @@
+use crate::prelude::*;
+
+// Maximum number of snapshots kept. When exceeded, the oldest are forgotten
+// (except for the starting position).
+const MAX_HISTORY: usize = 10000;
+
+/// Snapshot of the part of a Gamestate that changes during play.
+#[derive(Clone)]
+pub struct Snapshot {
+	pub map: Map,
+	pub mv: Vec<Mover>,
+	pub stats: Stats,
+}
+
+/// History of Gamestate snapshots, used for undo and rewind.
+/// The starting position is always kept.
+pub struct History {
+	snapshots: Vec<Snapshot>,
+}
+
+impl History {
+	/// New history, starting from the given snapshot.
+	pub fn new(start: Snapshot) -> History {
+		History { snapshots: vec![start] }
+	}
+
+	/// Record a snapshot.
+	pub fn push(&mut self, s: Snapshot) {
+		if self.snapshots.len() >= MAX_HISTORY {
+			self.snapshots.remove(1);
+		}
+		self.snapshots.push(s)
+	}
+
+	/// Forget the most recent snapshot, unless it is the starting position.
+	pub fn pop(&mut self) {
+		if self.snapshots.len() > 1 {
+			self.snapshots.pop();
+		}
+	}
+
+	/// The starting position.
+	pub fn first(&self) -> &Snapshot {
+		&self.snapshots[0]
+	}
+
+	/// The most recent snapshot.
+	pub fn last(&self) -> &Snapshot {
+		&self.snapshots[self.snapshots.len() - 1]
+	}
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug)]
 pub enum Key {
@@ -8,7 +10,7 @@ pub enum Key {
 	Down = 4,
 	A = 5,
 	B = 6,
-	X = 7,
+	Undo = 7,
 	Pause = 8,
 	ZoomIn = 9,
 	ZoomOut = 10,
@@ -16,6 +18,8 @@ pub enum Key {
 	PrevMap = 12,
 	Restart = 13,
 	NextMap = 14,
+	Wire = 15,
+	Ai = 16,
 }
 
 impl Key {
@@ -26,9 +30,9 @@ impl Key {
 }
 
 // KeyStates records which of the lowest 8 keys are currently pressed down.
-// these are the "dynamic" keys (movement, jumping) that need to be timed precisely
+// these are the "dynamic" keys (movement, jumping, undo) that need to be timed precisely
 // and indepdently of the OS key repeat rate.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct KeyStates {
 	pub down: [bool; 8],
 }
@@ -38,6 +42,14 @@ impl KeyStates {
 		KeyStates { down: [false; 8] }
 	}
 
+	/// Copy with key k pressed down.
+	/// Handy for scripting input, e.g. in headless simulation.
+	#[must_use]
+	pub fn with_down(mut self, k: Key) -> KeyStates {
+		self.set_down(k, true);
+		self
+	}
+
 	pub fn set_down(&mut self, k: Key, down: bool) {
 		let k = k.id();
 		if k < self.down.len() {
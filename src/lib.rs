@@ -1,16 +1,27 @@
+pub mod ai;
+pub mod ascii;
 pub mod block;
+pub mod campaign;
 pub mod color;
 pub mod dir;
 pub mod editor;
 pub mod encoding;
 pub mod error;
+pub mod event;
 pub mod gamestate;
+pub mod history;
 pub mod keys;
 pub mod map;
 pub mod mover;
 pub mod palette;
 pub mod prelude;
+pub mod progress;
+pub mod props;
 pub mod pt;
 pub mod rect;
+pub mod replay;
 pub mod sdl;
+pub mod solver;
+pub mod validate;
 pub mod viewport;
+pub mod xsb;
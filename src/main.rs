@@ -9,7 +9,6 @@ use sdl2::render::Texture;
 use sdl2::EventPump;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
 use std::path::PathBuf;
 use std::process;
 use std::time;
@@ -18,7 +17,7 @@ type Canvas = sdl2::render::Canvas<sdl2::video::Window>;
 
 pub fn main() {
 	if let Err(e) = main_() {
-		io::stderr().write_all(&e.to_string().into_bytes()).unwrap();
+		eprintln!("{}", e.report());
 		process::exit(1);
 	}
 }
@@ -39,29 +38,54 @@ pub fn main_() -> Result<()> {
 			if ext == "bmp" {
 				if let Some(stem) = f.file_stem() {
 					if let Ok(n) = stem.to_string_lossy().parse::<usize>() {
-						let surf = sdl2::surface::Surface::load_bmp(f)?;
+						let surf = sdl2::surface::Surface::load_bmp(&f).map_err(|e| Error::Texture(f.clone(), e))?;
 						while n >= textures.len() {
 							textures.push(None);
 						}
-						textures[n] = Some(tex_creator.create_texture_from_surface(&surf)?);
+						let tex = tex_creator
+							.create_texture_from_surface(&surf)
+							.map_err(|e| Error::Texture(f.clone(), e.to_string()))?;
+						textures[n] = Some(tex);
 					}
 				}
 			}
 		}
 	}
 	if textures.len() < NUM_BLOCKS {
-		return GenError::new(format!("failed to load {} textures from {}", NUM_BLOCKS, &tex_dir));
+		return Err(Error::Texture(tex_dir.into(), format!("failed to load {} textures", NUM_BLOCKS)));
 	}
 
 	let mut disp = Display::new(canvas, &textures);
 	let (w, h) = disp.size();
 
 	// load map from command line
+	// or play back a replay (*.replay).
 	let args: Vec<String> = env::args().skip(1).collect();
+	// without arguments, resume playing where the player left off.
 	let mut editor = match args.len() {
-		0 => Editor::new(w, h, find_map1(&PathBuf::from(map_dir))?, false),
-		1 => Editor::new(w, h, PathBuf::from(&args[0]), true),
-		_ => return GenError::new(format!("need 0 or 1 arguments, have {:?}", args)),
+		0 => {
+			let map_dir = PathBuf::from(map_dir);
+			let progress = Progress::load_default();
+			let file = match progress.as_ref().ok().and_then(|p| p.resume(&map_dir)) {
+				Some(f) => f,
+				None => find_map1(&map_dir)?,
+			};
+			let mut editor = Editor::new(w, h, file, false)?;
+			match progress {
+				Ok(p) => editor.track_progress(p),
+				Err(e) => eprintln!("not saving progress: {}", e.report()),
+			}
+			editor
+		}
+		1 if args[0].ends_with(".replay") => {
+			let replay = Replay::load(&PathBuf::from(&args[0]))?;
+			replay.load_map()?; // before opening the editor, which would create a missing map
+			let mut editor = Editor::new(w, h, replay.map.clone(), false)?;
+			editor.play_replay(&replay)?;
+			editor
+		}
+		1 => Editor::new(w, h, PathBuf::from(&args[0]), true)?,
+		_ => return Err(Error::Other(format!("need 0 or 1 arguments, have {:?}", args))),
 	};
 
 	// Main loop
@@ -104,8 +128,13 @@ pub fn main_() -> Result<()> {
 					);
 				}
 				Event::MouseWheel { x, y, .. } => editor.handle_mouse_wheel(x, y),
+				Event::TextInput { text, .. } => editor.handle_text(&text),
 				Event::KeyDown { keycode, .. } => {
 					if let Some(keycode) = keycode {
+						if editor.is_typing() {
+							handle_typing_key(&mut editor, keycode);
+							continue;
+						}
 						let k = keymap(keycode);
 						key_debounce.set_down(k, true);
 						key_states.set_down(k, true);
@@ -129,6 +158,17 @@ pub fn main_() -> Result<()> {
 	}
 }
 
+// Keys with a special meaning while typing text in the editor.
+fn handle_typing_key(editor: &mut Editor, sdl_key: sdl2::keyboard::Keycode) {
+	use sdl2::keyboard::Keycode;
+	match sdl_key {
+		Keycode::Return => editor.finish_typing(),
+		Keycode::Escape => editor.cancel_typing(),
+		Keycode::Backspace => editor.delete_char(),
+		_ => (),
+	}
+}
+
 fn keymap(sdl_key: sdl2::keyboard::Keycode) -> Key {
 	use sdl2::keyboard::Keycode;
 	match sdl_key {
@@ -141,8 +181,11 @@ fn keymap(sdl_key: sdl2::keyboard::Keycode) -> Key {
 		Keycode::Down => Key::Down,
 		Keycode::D => Key::Down,
 		Keycode::Space => Key::A,
+		Keycode::Return => Key::A,
 		Keycode::LAlt => Key::B,
 		Keycode::RAlt => Key::B,
+		Keycode::U => Key::Undo,
+		Keycode::Backspace => Key::Undo,
 		Keycode::Equals => Key::ZoomIn,
 		Keycode::Minus => Key::ZoomOut,
 		Keycode::P => Key::Pause,
@@ -150,16 +193,25 @@ fn keymap(sdl_key: sdl2::keyboard::Keycode) -> Key {
 		Keycode::N => Key::NextMap,
 		Keycode::M => Key::PrevMap,
 		Keycode::R => Key::Restart,
+		Keycode::C => Key::Wire,
+		Keycode::I => Key::Ai,
 		_ => Key::None,
 	}
 }
 
 fn init_window() -> Result<(Canvas, EventPump)> {
-	let context = sdl2::init()?;
-	let video = context.video()?;
-	let window = video.window("game", 1920 / 2, 1080 / 2).resizable().position_centered().build()?;
-	match window.into_canvas().present_vsync().build() {
-		Ok(c) => Ok((c, context.event_pump()?)),
-		Err(e) => Err(Box::new(e)),
-	}
+	let context = sdl2::init().map_err(Error::Sdl)?;
+	let video = context.video().map_err(Error::Sdl)?;
+	let window = video
+		.window("game", 1920 / 2, 1080 / 2)
+		.resizable()
+		.position_centered()
+		.build()
+		.map_err(|e| Error::Sdl(e.to_string()))?;
+	let canvas = window
+		.into_canvas()
+		.present_vsync()
+		.build()
+		.map_err(|e| Error::Sdl(e.to_string()))?;
+	Ok((canvas, context.event_pump().map_err(Error::Sdl)?))
 }
@@ -1,44 +1,256 @@
 use crate::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::ops;
 use std::result;
 
-/// Infinite 2D array of blocks.
-#[derive(Serialize, Deserialize)]
+/// A map: blocks in layers, optional cell properties, signal wires, game rules, plus metadata.
+/// Each grid cell holds one block per layer, so that, e.g., a key can lie on ice.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Map {
-	pub blocks: Vec<Vec<Block>>,
+	pub layers: [Layer; NUM_LAYERS], // FLOOR, OBJECTS
+	props: BTreeMap<Pt, Props>,      // cell properties. Never empty (see set_props).
+	wires: BTreeSet<(Pt, Pt)>,       // signal wires from trigger to receiver cell (see connect)
+	pub rules: Rules,
+	pub meta: Meta,
+}
+
+/// Layers, from bottom to top.
+pub const FLOOR: usize = 0; // terrain: bricks, water, ice, exits, ...
+pub const OBJECTS: usize = 1; // things on the terrain: keys, locks, buttons, arrows, movers, ...
+pub const NUM_LAYERS: usize = 2;
+
+/// Layer names, as used in map files and the editor.
+pub const LAYER_NAMES: [&str; NUM_LAYERS] = ["floor", "objects"];
+
+/// Infinite 2D array of blocks, covering the full i32 plane.
+/// Stored in square chunks, only where blocks have been set.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Layer {
+	chunks: BTreeMap<(i32, i32), Chunk>, // chunk position (see chunk_pos) -> blocks. Never all NONE.
+}
+
+// Chunks hold CHUNK x CHUNK blocks, row by row.
+const CHUNK: i32 = 16;
+type Chunk = Box<[Block; (CHUNK * CHUNK) as usize]>;
+
+/// Information about a map, for humans. All optional.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Meta {
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub title: String, // shown in-game
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub author: String,
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub hint: String, // shown in-game
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub par: Option<u32>, // par score, in moves
+}
+
+/// Game rules that may differ per map. Classic Sokoban by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+	#[serde(skip_serializing_if = "is_classic_push")]
+	pub push: u32, // how many movers in a row the player can push at once (1: classic Sokoban, 0: no pushing)
+}
+
+impl Default for Rules {
+	fn default() -> Rules {
+		Rules { push: 1 }
+	}
+}
+
+fn is_classic_push(push: &u32) -> bool {
+	*push == Rules::default().push
 }
 
 impl Map {
 	/// New empty map.
 	pub fn new() -> Map {
-		Map { blocks: Vec::new() }
+		Map {
+			layers: Default::default(),
+			props: BTreeMap::new(),
+			wires: BTreeSet::new(),
+			rules: Rules::default(),
+			meta: Meta::default(),
+		}
 	}
 
-	/// Set block at position p.
-	/// p must be strictly positive.
+	/// Set block at position p, in the block's default layer (see block_layer).
+	/// Use layers[..].set() to choose the layer.
 	pub fn set(&mut self, p: Pt, b: Block) {
-		let (x, y) = (p.0, p.1);
-		if x < 0 || y < 0 {
-			panic!("Map.set: Pt out of bounds: {:?}", p);
+		self.layers[block_layer(b)].set(p, b)
+	}
+
+	/// The blocks at position p, one per layer (FLOOR, OBJECTS).
+	pub fn at(&self, p: Pt) -> [Block; NUM_LAYERS] {
+		[self.layers[FLOOR][p], self.layers[OBJECTS][p]]
+	}
+
+	/// Does any layer have block b at position p?
+	pub fn has(&self, p: Pt, b: Block) -> bool {
+		self.at(p).contains(&b)
+	}
+
+	/// Direction of the arrow at position p, if any. The top-most layer wins.
+	pub fn arrow_dir(&self, p: Pt) -> Dir {
+		match self.at(p).iter().rev().find(|&&b| block_is_arrow(b)) {
+			Some(&b) => block_arrow_dir(b),
+			None => Dir::None,
+		}
+	}
+
+	/// Properties of the cell at position p (empty if none were set).
+	pub fn props(&self, p: Pt) -> &Props {
+		static EMPTY: Props = Props {
+			radius: None,
+			target: None,
+			message: String::new(),
+			ai: None,
+			seed: None,
+		};
+		self.props.get(&p).unwrap_or(&EMPTY)
+	}
+
+	/// Set the properties of the cell at position p. Empty properties remove them.
+	pub fn set_props(&mut self, p: Pt, props: Props) {
+		if props.is_empty() {
+			self.props.remove(&p);
+		} else {
+			self.props.insert(p, props);
+		}
+	}
+
+	/// All cells with properties, by position.
+	pub fn all_props(&self) -> impl Iterator<Item = (Pt, &Props)> {
+		self.props.iter().map(|(&p, props)| (p, props))
+	}
+
+	/// Wire the trigger (key, button, plate) at cell from to the receiver (lock, toggle, arrow) at cell to.
+	/// A wired trigger signals only the receivers it is wired to, at any distance (see block_signaled).
+	/// Unwired triggers affect all receivers of their color inside their action range.
+	pub fn connect(&mut self, from: Pt, to: Pt) {
+		self.wires.insert((from, to));
+	}
+
+	/// Remove the wire between cells from and to, if any.
+	pub fn disconnect(&mut self, from: Pt, to: Pt) {
+		self.wires.remove(&(from, to));
+	}
+
+	/// Is there a wire from cell from to cell to?
+	pub fn is_connected(&self, from: Pt, to: Pt) -> bool {
+		self.wires.contains(&(from, to))
+	}
+
+	/// All wires (from, to).
+	pub fn wires(&self) -> impl Iterator<Item = (Pt, Pt)> + '_ {
+		self.wires.iter().copied()
+	}
+
+	/// The cells wired to cell from.
+	pub fn wires_from(&self, from: Pt) -> Vec<Pt> {
+		let (min, max) = (Pt(i32::MIN, i32::MIN), Pt(i32::MAX, i32::MAX));
+		self.wires.range((from, min)..=(from, max)).map(|&(_, to)| to).collect()
+	}
+
+	/// All non-empty blocks with their position, in reading order (row by row, left to right).
+	/// Blocks at the same position are listed bottom layer first.
+	pub fn blocks(&self) -> Vec<(Pt, Block)> {
+		let mut blocks: Vec<(Pt, Block)> = self.layers.iter().flat_map(|l| l.blocks()).collect();
+		blocks.sort_by_key(|&(p, _)| (p.1, p.0)); // stable: keeps layer order
+		blocks
+	}
+
+	/// Bounding box (top-left, bottom-right, inclusive) of all non-empty blocks, in all layers.
+	/// None if the map is empty.
+	pub fn bounds(&self) -> Option<(Pt, Pt)> {
+		bounding_box(&self.blocks())
+	}
+
+	/// Checksum of the map's contents, to detect changes.
+	/// Independent of padding and metadata: only non-empty blocks, cell properties, wires and non-default rules contribute.
+	pub fn checksum(&self) -> u64 {
+		// FNV-1a, stable across platforms and compiler versions.
+		let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+		let mut hash = |bytes: &[u8]| {
+			for byte in bytes {
+				h ^= *byte as u64;
+				h = h.wrapping_mul(0x0100_0000_01b3);
+			}
+		};
+		for (p, b) in self.blocks() {
+			hash(&(p.0 as u32).to_le_bytes());
+			hash(&(p.1 as u32).to_le_bytes());
+			hash(&[b]);
+		}
+		for (p, props) in self.all_props() {
+			hash(&(p.0 as u32).to_le_bytes());
+			hash(&(p.1 as u32).to_le_bytes());
+			hash(props.to_string().as_bytes());
+		}
+		for (from, to) in self.wires() {
+			for p in &[from, to] {
+				hash(&(p.0 as u32).to_le_bytes());
+				hash(&(p.1 as u32).to_le_bytes());
+			}
 		}
-		let x = x as usize;
-		let y = y as usize;
+		if self.rules != Rules::default() {
+			hash(b"push");
+			hash(&self.rules.push.to_le_bytes());
+		}
+		h
+	}
+
+	/// Replace blocks inside range, in all layers.
+	pub fn replace<F: Fn(Block) -> Block>(&mut self, range: (Pt, Pt), f: F) {
+		for layer in &mut self.layers {
+			layer.replace(range, &f);
+		}
+	}
+}
 
-		if y >= self.blocks.len() {
-			self.blocks.reserve(y - self.blocks.len() + 1);
-			while y >= self.blocks.len() {
-				self.blocks.push(Vec::new());
+impl Layer {
+	/// Set block at position p.
+	pub fn set(&mut self, p: Pt, b: Block) {
+		let (key, i) = chunk_pos(p);
+		match self.chunks.get_mut(&key) {
+			Some(chunk) => {
+				chunk[i] = b;
+				if b == NONE && chunk.iter().all(|&b| b == NONE) {
+					self.chunks.remove(&key); // keep storage canonical, so that equal maps compare equal
+				}
+			}
+			None if b == NONE => (),
+			None => {
+				let mut chunk = Box::new([NONE; (CHUNK * CHUNK) as usize]);
+				chunk[i] = b;
+				self.chunks.insert(key, chunk);
 			}
 		}
-		if x >= self.blocks[y].len() {
-			self.blocks.reserve(x - self.blocks[y].len() + 1);
-			while x >= self.blocks[y].len() {
-				self.blocks[y].push(0);
+	}
+
+	/// All non-empty blocks with their position, in reading order (row by row, left to right).
+	pub fn blocks(&self) -> Vec<(Pt, Block)> {
+		let mut blocks = Vec::new();
+		for (&(cy, cx), chunk) in &self.chunks {
+			for (i, &b) in chunk.iter().enumerate() {
+				if b != NONE {
+					let i = i as i32;
+					blocks.push((Pt(cx * CHUNK + i % CHUNK, cy * CHUNK + i / CHUNK), b));
+				}
 			}
 		}
-		self.blocks[y][x] = b;
+		blocks.sort_by_key(|&(p, _)| (p.1, p.0));
+		blocks
+	}
+
+	/// Bounding box (top-left, bottom-right, inclusive) of all non-empty blocks.
+	/// None if the layer is empty.
+	pub fn bounds(&self) -> Option<(Pt, Pt)> {
+		bounding_box(&self.blocks())
 	}
 
 	pub fn replace<F: Fn(Block) -> Block>(&mut self, range: (Pt, Pt), f: F) {
@@ -55,38 +267,56 @@ impl Map {
 	}
 }
 
-impl ops::Index<Pt> for Map {
-	type Output = Block;
-	fn index(&self, p: Pt) -> &Block {
-		let (x, y) = (p.0, p.1);
+// Bounding box (top-left, bottom-right, inclusive) of blocks, None if there are none.
+fn bounding_box(blocks: &[(Pt, Block)]) -> Option<(Pt, Pt)> {
+	let mut min = Pt(i32::MAX, i32::MAX);
+	let mut max = Pt(i32::MIN, i32::MIN);
+	for (p, _) in blocks {
+		min = Pt(min.0.min(p.0), min.1.min(p.1));
+		max = Pt(max.0.max(p.0), max.1.max(p.1));
+	}
+	if min.0 > max.0 {
+		None
+	} else {
+		Some((min, max))
+	}
+}
 
-		// disallow 0 so that we can never bump into negative positions
-		// (where pos / GRID does not simply give the grid cell).
-		if x <= 0 || y <= 0 {
-			return &1; // Block outside of map
-		}
-		let x = x as usize;
-		let y = y as usize;
+/// Layer index for a name in LAYER_NAMES.
+pub fn layer_by_name(name: &str) -> Option<usize> {
+	LAYER_NAMES.iter().position(|&n| n == name)
+}
 
-		if y >= self.blocks.len() {
-			return &0;
-		}
-		if x >= self.blocks[y].len() {
-			return &0;
+impl ops::Index<Pt> for Layer {
+	type Output = Block;
+	fn index(&self, p: Pt) -> &Block {
+		let (key, i) = chunk_pos(p);
+		match self.chunks.get(&key) {
+			Some(chunk) => &chunk[i],
+			None => &NONE,
 		}
-
-		&self.blocks[y][x]
 	}
 }
 
+// Chunk key (chunk row, chunk column) and index inside the chunk for block position p.
+// Row first, so that chunks are ordered like reading order.
+fn chunk_pos(p: Pt) -> ((i32, i32), usize) {
+	let (cx, cy) = (p.0.div_euclid(CHUNK), p.1.div_euclid(CHUNK));
+	let (x, y) = (p.0.rem_euclid(CHUNK), p.1.rem_euclid(CHUNK));
+	((cy, cx), (y * CHUNK + x) as usize)
+}
+
 impl fmt::Display for Map {
 	fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-		for row in &self.blocks {
-			write!(f, "|")?;
-			for b in row {
-				write!(f, "{} ", b)?;
+		if let Some((min, max)) = self.bounds() {
+			for y in min.1..=max.1 {
+				write!(f, "|")?;
+				for x in min.0..=max.0 {
+					let [floor, objects] = self.at(Pt(x, y));
+					write!(f, "{} ", if objects != NONE { objects } else { floor })?;
+				}
+				writeln!(f)?;
 			}
-			write!(f, "\n")?;
 		}
 		Ok(())
 	}
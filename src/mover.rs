@@ -5,13 +5,15 @@ pub const ENEMY_SPEED_: i32 = 2; // units per tick (must divide GRID (48));
 
 /// A Mover is a block that can move over the map.
 /// E.g.: the player, crates, ice cubes, ...
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Mover {
 	pub pos: Pt,       // top-left position, in world coordinates.
 	pub inertia_: Dir, // direction currently moving in
 	pub intent: Dir,   // direction it wants to move in, if possible TODO: no need to save this
 	pub look: Dir,
 	pub typ: Block,
+	pub ai: Ai,   // how a pig picks its direction
+	pub rng: u64, // random number generator state, for Ai::Random
 }
 
 impl Mover {
@@ -28,7 +30,9 @@ impl Mover {
 		let mut intent = Dir::None;
 		match typ {
 			PLAYER => (),
+			PLAYER_ON_GOAL => typ = PLAYER,
 			CRATE => (),
+			CRATE_ON_GOAL => typ = CRATE,
 			ICECUBE => (),
 			PIG_L..=PIG_D => {
 				intent = LRUD[(typ - PIG_L) as usize];
@@ -43,6 +47,8 @@ impl Mover {
 			intent: intent,
 			look: Dir::Right,
 			typ: typ,
+			ai: Ai::default(),
+			rng: 0,
 		})
 	}
 
@@ -97,19 +103,30 @@ impl Mover {
 		}
 	}
 
-	pub fn can_walk(&self, b: Block) -> bool {
+	/// Can the mover enter a grid cell with these blocks (one per layer, see Map::at)?
+	/// Only if it can walk on every layer.
+	pub fn can_walk(&self, cell: [Block; NUM_LAYERS]) -> bool {
+		cell.iter().all(|&b| self.can_walk_on(b))
+	}
+
+	fn can_walk_on(&self, b: Block) -> bool {
 		match (self.typ(), b) {
 			(CRATE, WATER) => true,
 			(ICECUBE, WATER) => true,
+			(PLAYER, LAVA) => true,      // deadly
+			(PLAYER, WHIRLPOOL) => true, // deadly
 			_ => match b {
 				NONE => true,
 				KEY_B..=KEY_Y => true,
 				TOGGLE_OPEN_B..=TOGGLE_OPEN_Y => true,
 				BUTTON_B..=BUTTON_Y => true,
 				ARROW_L..=ARROW_D => true,
+				TELEPORTER => true,
+				PLATE_B..=PLATE_Y => true,
 				WATER => false,
 				ICE => true,
 				EXIT => true,
+				GOAL => true,
 				_ => false,
 			},
 		}
@@ -127,6 +144,11 @@ impl Mover {
 		}
 	}
 
+	/// Can the player push this mover, and the movers in front of it? (see Rules::push)
+	pub fn is_pushable(&self) -> bool {
+		matches!(self.typ, CRATE | ICECUBE)
+	}
+
 	pub fn on_bumped(&mut self, d: Dir) {
 		match self.typ {
 			CRATE | ICECUBE => self.intent = d,
@@ -1,15 +1,23 @@
+pub use crate::ai::*;
 pub use crate::block::*;
+pub use crate::campaign::*;
 pub use crate::color::*;
 pub use crate::dir::*;
 pub use crate::editor::*;
 pub use crate::error::*;
+pub use crate::event::*;
 pub use crate::gamestate::*;
+pub use crate::history::*;
 pub use crate::keys::*;
 pub use crate::map::*;
 pub use crate::mover::*;
+pub use crate::progress::*;
+pub use crate::props::*;
 pub use crate::pt::*;
 pub use crate::rect::*;
+pub use crate::replay::*;
 pub use crate::sdl::*;
+pub use crate::solver::*;
 pub use crate::viewport::*;
 
 pub type Tex = usize;
@@ -0,0 +1,140 @@
+use crate::encoding;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The player's progress, remembered between runs:
+/// completed maps, best scores, and the last map played.
+///
+/// Maps are identified by the checksum of their contents (see Map::checksum),
+/// so that progress survives maps being added or renamed.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Progress {
+	pub maps: Vec<MapRecord>, // maps played so far
+	pub last: Option<MapId>,  // map played most recently
+	#[serde(skip)]
+	file: PathBuf, // where to save
+}
+
+/// Identifies a map by checksum (and name, for humans and as a fallback).
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct MapId {
+	pub name: String, // file name when last played
+	pub hash: u64,    // Map::checksum
+}
+
+/// Progress on one map.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MapRecord {
+	pub id: MapId,
+	pub completed: bool,
+	pub best_moves: Option<u32>,
+	pub best_ticks: Option<u32>,
+}
+
+impl Progress {
+	/// Load progress from the default location in the user's data directory.
+	/// Starts afresh if there is no progress file yet.
+	pub fn load_default() -> Result<Progress> {
+		match data_dir() {
+			Some(dir) => Progress::load(&dir.join("progress.json")),
+			None => Err(Error::Other("progress: cannot determine user data directory".to_owned())),
+		}
+	}
+
+	/// Load progress from a file, starting afresh if it does not exist.
+	pub fn load(p: &Path) -> Result<Progress> {
+		let mut progress = if p.exists() {
+			let b = io::BufReader::new(File::open(p)?);
+			serde_json::from_reader(b)?
+		} else {
+			Progress::default()
+		};
+		progress.file = p.to_owned();
+		Ok(progress)
+	}
+
+	/// Save progress to the file it was loaded from.
+	pub fn save(&self) -> Result<()> {
+		if let Some(dir) = self.file.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let mut b = io::BufWriter::new(File::create(&self.file)?);
+		serde_json::to_writer_pretty(&mut b, self)?;
+		b.flush()?;
+		Ok(())
+	}
+
+	/// Remember that a map is being played.
+	pub fn set_last(&mut self, id: MapId) {
+		self.last = Some(id);
+	}
+
+	/// Remember that a map was completed, keeping the best scores.
+	pub fn complete(&mut self, id: MapId, stats: Stats) {
+		let rec = match self.maps.iter().position(|r| r.id.hash == id.hash) {
+			Some(i) => &mut self.maps[i],
+			None => {
+				self.maps.push(MapRecord {
+					id: id.clone(),
+					completed: false,
+					best_moves: None,
+					best_ticks: None,
+				});
+				self.maps.last_mut().unwrap()
+			}
+		};
+		rec.id = id; // name may have changed
+		rec.completed = true;
+		rec.best_moves = Some(rec.best_moves.map_or(stats.moves, |m| m.min(stats.moves)));
+		rec.best_ticks = Some(rec.best_ticks.map_or(stats.ticks, |t| t.min(stats.ticks)));
+	}
+
+	/// Record for the map with given checksum, if played before.
+	pub fn record(&self, hash: u64) -> Option<&MapRecord> {
+		self.maps.iter().find(|r| r.id.hash == hash)
+	}
+
+	/// Find the map to resume playing in directory dir:
+	/// the last map played, identified by contents, or else by name.
+	/// Only loads other maps than the one with the same name if that one has changed.
+	pub fn resume(&self, dir: &Path) -> Option<PathBuf> {
+		let last = self.last.as_ref()?;
+		let maps = encoding::ls_maps(dir).ok()?;
+		let same = |f: &&PathBuf| encoding::load_unchecked(f).map(|m| m.checksum() == last.hash).unwrap_or(false);
+		let by_name = maps.iter().find(|f| encoding::file_name(f) == last.name);
+		match by_name {
+			Some(f) if same(&f) => Some(f.clone()),
+			_ => maps.iter().find(same).or(by_name).cloned(),
+		}
+	}
+}
+
+impl MapId {
+	/// Identify the map in file, with given checksum.
+	pub fn new(file: &Path, hash: u64) -> MapId {
+		MapId {
+			name: encoding::file_name(file),
+			hash,
+		}
+	}
+}
+
+// Directory for the game's data, following platform conventions.
+fn data_dir() -> Option<PathBuf> {
+	let base = if cfg!(windows) {
+		env::var_os("APPDATA").map(PathBuf::from)
+	} else if cfg!(target_os = "macos") {
+		env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+	} else {
+		env::var_os("XDG_DATA_HOME")
+			.map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+	};
+	base.map(|b| b.join("rusty-mazes"))
+}
@@ -0,0 +1,135 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+/// Optional parameters of a grid cell, for the blocks in it.
+/// E.g. a key with a custom action radius, a message shown when the player steps on the cell,
+/// or the behaviour of a pig starting there.
+/// Written as text like "radius=5; target=3 4; message=Mind the gap; ai=chase; seed=7" (see Display, FromStr).
+/// In messages, ';' and '\' are escaped with a backslash.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Props {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub radius: Option<i32>, // action radius of an unwired key, button or plate, in blocks. See Gamestate::action_range.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub target: Option<Pt>, // partner of a teleporter, grid position
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub message: String, // shown when the player enters the cell
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ai: Option<Ai>, // behaviour of a pig starting in the cell (default: Ai::Patrol)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub seed: Option<u64>, // random seed of a pig starting in the cell (default: see ai::default_seed)
+}
+
+/// Largest action radius a cell can set, in blocks.
+/// Keeps the action range (see Gamestate::action_range) small enough to walk through quickly.
+pub const MAX_RADIUS: i32 = 256;
+
+impl Props {
+	/// Are all properties unset?
+	pub fn is_empty(&self) -> bool {
+		*self == Props::default()
+	}
+
+	/// Check that the values are in range: the radius must be between 0 and MAX_RADIUS.
+	pub fn check(&self) -> Result<()> {
+		match self.radius {
+			Some(r) if !(0..=MAX_RADIUS).contains(&r) => Err(Error::Parse(format!("radius must be between 0 and {}: {}", MAX_RADIUS, r))),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl fmt::Display for Props {
+	fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+		let mut fields = Vec::new();
+		if let Some(radius) = self.radius {
+			fields.push(format!("radius={}", radius));
+		}
+		if let Some(target) = self.target {
+			fields.push(format!("target={} {}", target.0, target.1));
+		}
+		if !self.message.is_empty() {
+			fields.push(format!("message={}", escape(&self.message)));
+		}
+		if let Some(ai) = self.ai {
+			fields.push(format!("ai={}", ai));
+		}
+		if let Some(seed) = self.seed {
+			fields.push(format!("seed={}", seed));
+		}
+		write!(f, "{}", fields.join("; "))
+	}
+}
+
+impl FromStr for Props {
+	type Err = Error;
+
+	/// Parse "key=value" pairs separated by ';'. Inverse of Display.
+	///
+	///     use game::prelude::*;
+	///     let p: Props = "radius=5; message=Hello\\; bye".parse().unwrap();
+	///     assert_eq!(p.radius, Some(5));
+	///     assert_eq!(p.message, "Hello; bye");
+	///     assert_eq!(p.to_string(), "radius=5; message=Hello\\; bye");
+	fn from_str(s: &str) -> Result<Props> {
+		let mut props = Props::default();
+		for field in split_fields(s)?.iter().map(|f| f.trim()).filter(|f| !f.is_empty()) {
+			let (key, value) = match field.find('=') {
+				Some(i) => (field[..i].trim(), field[i + 1..].trim()),
+				None => return Err(Error::Parse(format!("property needs \"key=value\": {}", field))),
+			};
+			match key {
+				"radius" => match value.parse() {
+					Ok(r) => props.radius = Some(r),
+					Err(_) => return Err(Error::Parse(format!("radius: invalid number: {}", value))),
+				},
+				"target" => props.target = Some(parse_pt(value)?),
+				"message" => props.message = value.into(),
+				"ai" => props.ai = Some(value.parse()?),
+				"seed" => match value.parse() {
+					Ok(s) => props.seed = Some(s),
+					Err(_) => return Err(Error::Parse(format!("seed: invalid number: {}", value))),
+				},
+				_ => return Err(Error::Parse(format!("unknown property \"{}\"", key))),
+			}
+		}
+		props.check()?;
+		Ok(props)
+	}
+}
+
+// Escape ';' and '\\' with a backslash, so that the text can be a property value.
+fn escape(text: &str) -> String {
+	text.replace('\\', "\\\\").replace(';', "\\;")
+}
+
+// Split text on ';', except where escaped. Removes the escapes (see escape).
+fn split_fields(s: &str) -> Result<Vec<String>> {
+	let mut fields = vec![String::new()];
+	let mut chars = s.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			';' => fields.push(String::new()),
+			'\\' => match chars.next() {
+				Some(c) => fields.last_mut().unwrap().push(c),
+				None => return Err(Error::Parse(format!("property ends in '\\': {}", s))),
+			},
+			c => fields.last_mut().unwrap().push(c),
+		}
+	}
+	Ok(fields)
+}
+
+// Parse a grid position: "x y".
+fn parse_pt(s: &str) -> Result<Pt> {
+	let xy: Vec<&str> = s.split_whitespace().collect();
+	if let [x, y] = xy[..] {
+		if let (Ok(x), Ok(y)) = (x.parse(), y.parse()) {
+			return Ok(Pt(x, y));
+		}
+	}
+	Err(Error::Parse(format!("position needs 2 numbers: {}", s)))
+}
@@ -1,10 +1,11 @@
 use crate::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use std::fmt;
 use std::ops;
 
 /// A 2D point.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Pt(pub i32, pub i32);
 
 impl Pt {
@@ -32,13 +33,20 @@ impl Pt {
 	#[inline]
 	pub fn grid(self) -> Pt {
 		assert!(self.aligned());
-		self / GRID
+		self.div_floor(GRID)
 	}
 
 	/// Approximate grid position. OK if not aligned.
 	#[inline]
 	pub fn approx_grid(self) -> Pt {
-		(self + Pt(GRID / 2, GRID / 2)) / GRID
+		(self + Pt(GRID / 2, GRID / 2)).div_floor(GRID)
+	}
+
+	/// Division rounding towards negative infinity (unlike `/`, which rounds towards zero).
+	/// E.g. the grid cell containing a world position: Pt(-1, 1).div_floor(GRID) == Pt(-1, 0).
+	#[inline]
+	pub fn div_floor(self, d: i32) -> Pt {
+		Pt(self.0.div_euclid(d), self.1.div_euclid(d))
 	}
 }
 
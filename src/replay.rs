@@ -0,0 +1,120 @@
+use crate::encoding;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Recorded input for one attempt at playing a map.
+/// Feeding the inputs back into a fresh Gamestate reproduces the run exactly.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+	pub map: PathBuf,            // map file that was played
+	pub hash: u64,               // Map::checksum of the map when recorded
+	keys: Vec<(KeyStates, u32)>, // recorded KeyStates, run-length encoded (keys, repeat count)
+}
+
+impl Replay {
+	/// Start a new, empty recording for a (staged) map.
+	pub fn new(map: PathBuf, staging: &Map) -> Replay {
+		Replay {
+			map,
+			hash: staging.checksum(),
+			keys: Vec::new(),
+		}
+	}
+
+	/// Record the KeyStates for one tick.
+	pub fn record(&mut self, keys: KeyStates) {
+		if let Some(last) = self.keys.last_mut() {
+			if last.0 == keys {
+				last.1 += 1;
+				return;
+			}
+		}
+		self.keys.push((keys, 1))
+	}
+
+	/// The recorded KeyStates, one per tick.
+	pub fn keys(&self) -> Vec<KeyStates> {
+		self.keys
+			.iter()
+			.flat_map(|(k, n)| std::iter::repeat_n(*k, *n as usize))
+			.collect()
+	}
+
+	/// Load the staged map that was played,
+	/// checking that it has not changed since recording.
+	pub fn load_map(&self) -> Result<Map> {
+		let staging = encoding::load(&self.map)?;
+		if staging.checksum() != self.hash {
+			return Err(Error::Other(format!("replay: map {} has changed since recording", self.map.to_string_lossy())));
+		}
+		Ok(staging)
+	}
+
+	/// Does playing back the replay complete the map (reach the exit, or cover all goals)?
+	/// Runs headless, e.g. to check that a level can be solved.
+	pub fn solves(&self) -> Result<bool> {
+		let mut g = Gamestate::from_map(&self.load_map()?);
+		g.run(self.keys());
+		Ok(g.status() == Status::Complete)
+	}
+
+	/// Load a replay in JSON format.
+	pub fn load(p: &Path) -> Result<Replay> {
+		let f = File::open(p)?;
+		let b = io::BufReader::new(f);
+		let replay = serde_json::from_reader(b)?;
+		Ok(replay)
+	}
+
+	/// Save a replay in JSON format.
+	pub fn save(&self, p: &Path) -> Result<()> {
+		let f = File::create(p)?;
+		let mut b = io::BufWriter::new(f);
+		serde_json::to_writer(&mut b, self)?;
+		b.flush()?;
+		println!("wrote {}", p.to_string_lossy());
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ascii;
+	use std::fs;
+
+	#[test]
+	fn record_and_play_back() {
+		let dir = std::env::temp_dir();
+		let map_file = dir.join(format!("game-test-{}-replay.txt", std::process::id()));
+		let replay_file = map_file.with_extension("replay");
+		encoding::save(&ascii::parse("#P  E#\n").unwrap(), &map_file).unwrap();
+
+		// walk to the exit, and stop halfway
+		let right = KeyStates::new().with_down(Key::Right);
+		let cells = |n: usize| std::iter::repeat_n(right, n * (GRID / PLAYER_SPEED_) as usize);
+		let mut g = Gamestate::load(map_file.clone()).unwrap();
+		g.run(cells(3));
+		assert_eq!(g.status(), Status::Complete);
+		assert!(!replay_file.exists()); // saved only on request
+		let mut halfway = Gamestate::load(map_file.clone()).unwrap();
+		halfway.run(cells(1));
+
+		g.replay().save(&replay_file).unwrap();
+		let replay = Replay::load(&replay_file).unwrap();
+		assert_eq!(replay.keys().len(), 3 * (GRID / PLAYER_SPEED_) as usize);
+		assert!(replay.solves().unwrap());
+		assert!(!halfway.replay().solves().unwrap());
+
+		// the map may not change after recording
+		encoding::save(&ascii::parse("#P   E#\n").unwrap(), &map_file).unwrap();
+		assert!(replay.solves().is_err());
+
+		fs::remove_file(map_file).unwrap();
+		fs::remove_file(replay_file).unwrap();
+	}
+}
@@ -10,13 +10,27 @@ use sdl2::render::Texture;
 pub struct Display<'a> {
 	canvas: Canvas,
 	textures: &'a [Option<Texture<'a>>],
+	title: String, // window title
 }
 
 type Canvas = sdl2::render::Canvas<sdl2::video::Window>;
 
 impl<'a> Display<'a> {
 	pub fn new(canvas: Canvas, textures: &'a [Option<Texture<'a>>]) -> Display<'a> {
-		Display { canvas, textures }
+		Display {
+			canvas,
+			textures,
+			title: String::new(),
+		}
+	}
+
+	/// Set the window title (if changed).
+	/// NUL characters (which may come from map files) are dropped, SDL cannot show them.
+	pub fn set_title(&mut self, title: &str) {
+		if title != self.title {
+			self.title = title.to_owned();
+			self.canvas.window_mut().set_title(&title.replace('\0', "")).unwrap() // only fails on NUL
+		}
 	}
 
 	pub fn size(&self) -> (i32, i32) {
@@ -34,6 +48,11 @@ impl<'a> Display<'a> {
 		self.canvas.fill_rect(rect::Rect::new(pos.0, pos.1, w as u32, h as u32)).unwrap()
 	}
 
+	pub fn draw_line(&mut self, c: Color, from: Pt, to: Pt) {
+		self.canvas.set_draw_color(sdl_color(c));
+		self.canvas.draw_line((from.0, from.1), (to.0, to.1)).unwrap()
+	}
+
 	pub fn copy_tex(&mut self, tex: usize, pos: Pt, w: i32, h: i32) {
 		let tex = match self.textures[tex].as_ref() {
 			Some(t) => t,
@@ -0,0 +1,124 @@
+use crate::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+// Give up on a move if the player does not become grid-aligned within this many ticks.
+// (e.g. stuck in an endless loop of arrows).
+const MAX_MOVE_TICKS: usize = 10000;
+
+// Waiting lasts as long as a move.
+const WAIT_TICKS: usize = (GRID / PLAYER_SPEED_) as usize;
+const MOVES_OR_WAIT: [Dir; 5] = [Dir::Left, Dir::Right, Dir::Up, Dir::Down, Dir::None];
+
+/// Find the shortest sequence of moves that takes the player from the start of a (staged) map to the exit.
+///
+/// Searches breadth-first through the states where the player is aligned to the grid,
+/// i.e. the positions of the player and all other movers, plus the map (keys, locks, toggles, ...).
+/// Moves are played out by the real game rules (Gamestate::tick), so that ice, arrows,
+/// water, etc. behave exactly as in the game.
+///
+/// Waiting (Dir::None) is only tried when the map can change without input (see is_restless),
+/// e.g. to let a pig walk by.
+///
+/// Returns None if no solution exists, or none was found after exploring max_states states.
+///
+///     use game::prelude::*;
+///     let mut staging = Map::new();
+///     staging.set(Pt(1, 1), PLAYER);
+///     staging.set(Pt(2, 1), BRICK);
+///     staging.set(Pt(2, 3), EXIT);
+///     let moves = solve(&staging, 1000).unwrap();
+///     assert_eq!(moves.len(), 3); // e.g. down, down, right
+///
+pub fn solve(staging: &Map, max_states: usize) -> Option<Vec<Dir>> {
+	let mut g = Gamestate::from_map(staging);
+	if g.at_exit() {
+		return Some(vec![]);
+	}
+
+	// explored states and how we got there: (state, parent index, move from parent)
+	let mut nodes = vec![(g.snapshot(), 0, Dir::None)];
+	let mut seen = HashSet::new();
+	seen.insert(state_key(&nodes[0].0));
+	let mut queue = VecDeque::new();
+	queue.push_back(0);
+
+	while let Some(parent) = queue.pop_front() {
+		g.restore(nodes[parent].0.clone());
+		let moves = if is_restless(&g) { &MOVES_OR_WAIT[..] } else { &LRUD[..] };
+		for dir in moves {
+			g.restore(nodes[parent].0.clone());
+			let start = g.player().pos();
+			if !play_move(&mut g, *dir) {
+				continue;
+			}
+			if *dir != Dir::None && g.player().pos() == start && g.status() == Status::Playing {
+				continue; // blocked: at best the same as waiting
+			}
+			match g.status() {
+				Status::Playing => (),
+				Status::Complete => {
+					nodes.push((g.snapshot(), parent, *dir));
+					return Some(moves_to(&nodes, nodes.len() - 1));
+				}
+				_ => continue, // dead end
+			}
+			let s = g.snapshot();
+			if !seen.insert(state_key(&s)) {
+				continue;
+			}
+			nodes.push((s, parent, *dir));
+			if nodes.len() >= max_states {
+				return None;
+			}
+			queue.push_back(nodes.len() - 1);
+		}
+	}
+	None
+}
+
+// Hold down the key for direction dir until the player is aligned to the grid again
+// (or dies). Returns false if the player never aligns.
+// Dir::None waits, pressing no keys for WAIT_TICKS.
+fn play_move(g: &mut Gamestate, dir: Dir) -> bool {
+	if dir == Dir::None {
+		for _ in 0..WAIT_TICKS {
+			g.step(KeyStates::new());
+		}
+		return g.player().aligned() || g.status() != Status::Playing;
+	}
+	let keys = KeyStates::new().with_down(dir.key());
+	g.step(keys);
+	for _ in 0..MAX_MOVE_TICKS {
+		if g.player().aligned() || g.status() != Status::Playing {
+			return true;
+		}
+		g.step(keys);
+	}
+	false
+}
+
+// Can the map change without input? E.g. pigs walking, crates sliding over ice.
+fn is_restless(g: &Gamestate) -> bool {
+	g.mv[1..].iter().any(|m| m.typ() == PIG || m.inertia() != Dir::None)
+}
+
+// Sequence of moves leading from the starting state to node i.
+fn moves_to(nodes: &[(Snapshot, usize, Dir)], mut i: usize) -> Vec<Dir> {
+	let mut moves = Vec::new();
+	while i != 0 {
+		moves.push(nodes[i].2);
+		i = nodes[i].1;
+	}
+	moves.reverse();
+	moves
+}
+
+// Everything that determines how a state evolves: the map, and per mover
+// position, type, intent, inertia and random number generator state (see Ai::Random).
+// (Where movers look is purely cosmetic, so it does not count.)
+type StateKey = (Map, Vec<(Pt, Block, Dir, Dir, u64)>);
+
+fn state_key(s: &Snapshot) -> StateKey {
+	let movers = s.mv.iter().map(|m| (m.pos, m.typ, m.intent, m.inertia_, m.rng)).collect();
+	(s.map.clone(), movers)
+}
@@ -0,0 +1,126 @@
+use crate::prelude::*;
+use std::fmt;
+
+/// A structural problem with a (staged) map.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+	NoExit,                     // neither an exit nor goals
+	TooFewCrates(usize, usize), // number of goals, crates
+	NoPlayer,
+	MultiplePlayers(Vec<Pt>),   // grid positions of all players
+	UnmatchedKey(Pt),           // key without a lock of the same color in its action range
+	UnpairedTeleporter(Pt),     // teleporter whose target is not a teleporter
+	LooseWire(Pt, Pt),          // wire not from a trigger, or not to a receiver (see Map::connect)
+	UnknownBlock(Pt, Block),    // block id without meaning
+}
+
+/// Check a staged map (i.e. with movers still as blocks) for structural problems.
+/// An empty list means the map looks fine.
+pub fn check(staging: &Map) -> Vec<Problem> {
+	let mut problems = Vec::new();
+	let mut players = Vec::new();
+	let mut have_exit = false;
+	let mut goals = 0;
+	let mut crates = 0;
+
+	for (grid, blk) in staging.blocks() {
+		match blk {
+			PLAYER => players.push(grid),
+			PLAYER_ON_GOAL => {
+				players.push(grid);
+				goals += 1;
+			}
+			EXIT => have_exit = true,
+			GOAL => goals += 1,
+			CRATE => crates += 1,
+			CRATE_ON_GOAL => {
+				goals += 1;
+				crates += 1;
+			}
+			KEY_B..=KEY_Y => {
+				let lock = blk - 4; // see block.rs
+				let wired = !staging.wires_from(grid).is_empty();
+				if !wired && !has_block(staging, Gamestate::action_range(staging, grid), lock) {
+					problems.push(Problem::UnmatchedKey(grid))
+				}
+			}
+			TELEPORTER => match staging.props(grid).target {
+				Some(target) if staging.has(target, TELEPORTER) => (),
+				_ => problems.push(Problem::UnpairedTeleporter(grid)),
+			},
+			_ if !block_is_known(blk) => problems.push(Problem::UnknownBlock(grid, blk)),
+			_ => (),
+		}
+	}
+
+	for (from, to) in staging.wires() {
+		let is_trigger = staging.at(from).iter().any(|&b| block_is_trigger(b));
+		let is_receiver = staging.at(to).iter().any(|&b| block_signaled(b) != b);
+		if !is_trigger || !is_receiver {
+			problems.push(Problem::LooseWire(from, to));
+		}
+	}
+
+	if !have_exit && goals == 0 {
+		problems.push(Problem::NoExit);
+	}
+	if crates < goals {
+		problems.push(Problem::TooFewCrates(goals, crates));
+	}
+	match players.len() {
+		0 => problems.push(Problem::NoPlayer),
+		1 => (),
+		_ => problems.push(Problem::MultiplePlayers(players)),
+	}
+	problems
+}
+
+impl Problem {
+	/// Does the problem make the map unplayable? Such maps are rejected on load.
+	pub fn is_fatal(&self) -> bool {
+		matches!(self, Problem::NoPlayer | Problem::MultiplePlayers(_) | Problem::UnknownBlock(..))
+	}
+}
+
+// Does block b occur in the range of grid positions?
+fn has_block(m: &Map, range: (Pt, Pt), b: Block) -> bool {
+	for y in (range.0).1..(range.1).1 {
+		for x in (range.0).0..(range.1).0 {
+			if m.has(Pt(x, y), b) {
+				return true;
+			}
+		}
+	}
+	false
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+		match self {
+			Problem::NoExit => write!(f, "no exit (or goals)"),
+			Problem::TooFewCrates(goals, crates) => write!(f, "{} goals but only {} crates", goals, crates),
+			Problem::NoPlayer => write!(f, "no player"),
+			Problem::MultiplePlayers(pos) => {
+				let pos: Vec<String> = pos.iter().map(|&p| row_col(p)).collect();
+				write!(f, "{} players, at {}", pos.len(), pos.join("; "))
+			}
+			Problem::UnmatchedKey(pos) => write!(f, "{}: key has no matching lock in range", row_col(*pos)),
+			Problem::UnpairedTeleporter(pos) => write!(f, "{}: teleporter has no partner", row_col(*pos)),
+			Problem::LooseWire(from, to) => write!(
+				f,
+				"wire from {} to {}: needs a trigger (key, button, plate) at its start and a receiver (lock, toggle, arrow) at its end",
+				row_col(*from),
+				row_col(*to)
+			),
+			Problem::UnknownBlock(pos, blk) if *blk as usize >= NUM_BLOCKS => {
+				write!(f, "{}: unknown block {} (beyond NUM_BLOCKS = {})", row_col(*pos), blk, NUM_BLOCKS)
+			}
+			Problem::UnknownBlock(pos, blk) => write!(f, "{}: unknown block {}", row_col(*pos), blk),
+		}
+	}
+}
+
+// Grid position as shown to map authors.
+fn row_col(p: Pt) -> String {
+	format!("row {}, column {}", p.1, p.0)
+}
@@ -66,6 +66,7 @@ impl Viewport {
 	}
 
 	/// Render the map as seen through the viewport.
+	/// Layers are drawn bottom to top.
 	pub fn render_map(&self, disp: &mut Display, map: &Map) {
 		// hack: speed-up zoomed-out (slow) view by not rendering background blocks
 		// thus, must clear background first.
@@ -77,27 +78,40 @@ impl Viewport {
 		// range of blocks visible in viewport
 		let s = (GRID * self.zoom().0) / self.zoom().1;
 		let (min, max) = self.view_rect(disp);
-		let min = min / GRID - Pt(1, 1);
-		let max = max / GRID + Pt(1, 1);
+		let min = min.div_floor(GRID) - Pt(1, 1);
+		let max = max.div_floor(GRID) + Pt(1, 1);
 
 		for y in min.1..max.1 {
 			for x in min.0..max.0 {
 				let p_grid = Pt(x, y);
 				let p_world = p_grid * GRID;
-				let blk = map[p_grid];
 
 				// clear background first (only really needed for non-opaque sprites).
 				// hack: skip if zoomed out (slow)
 				if !zoomed {
 					disp.copy_tex(0, self.to_canvas(p_world), s, s);
 				}
-				if blk != 0 {
-					disp.copy_tex(blk as usize, self.to_canvas(p_world), s, s);
+				for &blk in &map.at(p_grid) {
+					if blk != 0 {
+						disp.copy_tex(blk as usize, self.to_canvas(p_world), s, s);
+					}
 				}
 			}
 		}
 	}
 
+	/// Render signal wires (see Map::connect) as lines between cell centers,
+	/// with a dot at the receiving end.
+	pub fn render_wires(&self, disp: &mut Display, map: &Map) {
+		let center = Pt(GRID / 2, GRID / 2);
+		let dot = (GRID * self.zoom().0) / self.zoom().1 / 8 + 1;
+		for (from, to) in map.wires() {
+			let (from, to) = (self.to_canvas(from * GRID + center), self.to_canvas(to * GRID + center));
+			disp.draw_line(Color(255, 0, 255, 255), from, to);
+			disp.fill_rect(Color(255, 0, 255, 255), to - Pt(dot, dot) / 2, dot, dot);
+		}
+	}
+
 	pub fn render_movers(&self, disp: &mut Display, m: &[Mover]) {
 		let s = (GRID * self.zoom().0) / self.zoom().1;
 		for m in m {
@@ -0,0 +1,122 @@
+//! Importer for classic Sokoban levels in the de-facto XSB format (.xsb, .sok).
+//!
+//! A file holds one or more levels, separated by non-board lines:
+//!
+//! ```text
+//! ; 1
+//!   #####
+//! ###   #
+//! #.@$  #
+//! #######
+//! Title: First
+//! Author: Someone
+//! ```
+//!
+//! Board characters: '#' wall, '@' player, '$' crate, '.' goal, '*' crate on goal, '+' player on goal,
+//! ' ', '-' or '_' floor. Also accepted: 'p', 'P', 'b', 'B' for '@', '+', '$', '*',
+//! run-length encoding (e.g. "4#") and '|' as row separator.
+//! "Title:" and "Author:" lines after a level set its metadata,
+//! otherwise the last comment line before a level becomes its title.
+use crate::prelude::*;
+
+// Board position of the top-left character of a level. Leaves a margin, like hand-made maps.
+const ORIGIN: Pt = Pt(1, 1);
+
+/// Parse all levels in a file in XSB format, as staged maps.
+///
+///     use game::prelude::*;
+///     let levels = game::xsb::parse("#####\n#@$.#\n#####\nTitle: tiny\n").unwrap();
+///     assert_eq!(levels.len(), 1);
+///     assert_eq!(levels[0].meta.title, "tiny");
+///     assert!(levels[0].has(Pt(4, 2), GOAL));
+pub fn parse(text: &str) -> Result<Vec<Map>> {
+	let mut levels = Vec::new();
+	let mut board = Vec::new(); // lines of the level being read
+	let mut title = String::new(); // last comment, title of the next level
+
+	for line in text.lines() {
+		if is_board_line(line) {
+			board.push(line);
+			continue;
+		}
+		if !board.is_empty() {
+			let mut map = parse_board(&board);
+			map.meta.title = std::mem::take(&mut title);
+			levels.push(map);
+			board.clear();
+		}
+
+		let line = line.trim();
+		if let Some(value) = strip_key(line, "title:") {
+			if let Some(map) = levels.last_mut() {
+				map.meta.title = value.into();
+			}
+		} else if let Some(value) = strip_key(line, "author:") {
+			if let Some(map) = levels.last_mut() {
+				map.meta.author = value.into();
+			}
+		} else if !line.is_empty() && !line.contains(':') {
+			title = line.trim_start_matches(';').trim().into();
+		}
+	}
+	if !board.is_empty() {
+		let mut map = parse_board(&board);
+		map.meta.title = title;
+		levels.push(map);
+	}
+
+	if levels.is_empty() {
+		return Err(Error::Parse("no Sokoban levels found".into()));
+	}
+	Ok(levels)
+}
+
+// Value of a "Key: value" line, if the key matches (case insensitive).
+fn strip_key<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+	match line.get(..key.len()) {
+		Some(k) if k.eq_ignore_ascii_case(key) => Some(line[key.len()..].trim()),
+		_ => None,
+	}
+}
+
+// Is this line part of a board (as opposed to a title, comment, ...)?
+fn is_board_line(line: &str) -> bool {
+	line.contains('#') && line.chars().all(|c| " -_#@+$*.pPbB|0123456789".contains(c))
+}
+
+// Convert the lines of one level into a staged map.
+fn parse_board(board: &[&str]) -> Map {
+	let mut map = Map::new();
+	let mut pos = ORIGIN;
+	let mut count = 0;
+	for line in board {
+		for c in line.chars() {
+			let b = match c {
+				'0'..='9' => {
+					count = count * 10 + (c as i32 - '0' as i32);
+					continue;
+				}
+				'|' => {
+					pos = Pt(ORIGIN.0, pos.1 + 1);
+					continue;
+				}
+				'#' => BRICK,
+				'@' | 'p' => PLAYER,
+				'+' | 'P' => PLAYER_ON_GOAL,
+				'$' | 'b' => CRATE,
+				'*' | 'B' => CRATE_ON_GOAL,
+				'.' => GOAL,
+				_ => NONE, // floor
+			};
+			for _ in 0..count.max(1) {
+				if b != NONE {
+					map.set(pos, b);
+				}
+				pos.0 += 1;
+			}
+			count = 0;
+		}
+		pos = Pt(ORIGIN.0, pos.1 + 1);
+	}
+	map
+}